@@ -0,0 +1,376 @@
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+/// Everything captured from a finished (or killed) command.
+pub struct Output {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: Option<i32>,
+    /// The timeout that expired, if the command was killed for running too
+    /// long. `stdout` and `stderr` then only hold what was written up to that
+    /// point.
+    pub timed_out: Option<Duration>,
+    /// Why the command couldn't be started, if it couldn't. Everything else
+    /// is then empty.
+    pub not_started: Option<String>,
+}
+
+/// The command a test runs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Input {
+    /// A script run with bash.
+    Shell(String),
+    /// A program and its arguments, run directly without a shell. Never
+    /// empty once the test has been validated.
+    Argv(Vec<String>),
+}
+
+pub fn build(
+    test: &super::Test,
+    suite: &super::Suite,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+) -> Command {
+    match test.input {
+        Input::Shell(ref script) => shell(script, Some(test), suite, base_dir, tmpdir),
+        Input::Argv(ref argv) => {
+            let mut command = Command::new(&argv[0]);
+            command.args(&argv[1..]);
+
+            configure(&mut command, Some(test), suite, base_dir, tmpdir);
+
+            command
+        }
+    }
+}
+
+/// Runs `script` with bash, in the working directory and environment of
+/// `test`. Without a test, only the file-level settings apply.
+pub fn shell(
+    script: &str,
+    test: Option<&super::Test>,
+    suite: &super::Suite,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+) -> Command {
+    let mut command = Command::new("bash");
+    command.arg("-c").arg(script);
+
+    configure(&mut command, test, suite, base_dir, tmpdir);
+
+    command
+}
+
+/// Sets the working directory and environment of `test` on `command`.
+///
+/// `tmpdir` is the test's temporary directory, if it has one. It's exposed
+/// as `$CLI_TEST_TMPDIR`, and used as the working directory unless the test
+/// sets its own `cwd`.
+fn configure(
+    command: &mut Command,
+    test: Option<&super::Test>,
+    suite: &super::Suite,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+) {
+    if let Some(dir) = working_dir(test, base_dir, tmpdir) {
+        command.current_dir(dir);
+    }
+
+    let env_clear = test
+        .and_then(|test| test.env_clear)
+        .unwrap_or(suite.env_clear);
+    if env_clear {
+        command.env_clear();
+    }
+
+    // Test-level variables are applied last so they override file-level ones.
+    command.envs(&suite.env);
+    if let Some(test) = test {
+        command.envs(&test.env);
+    }
+
+    if let Some(tmpdir) = tmpdir {
+        command.env("CLI_TEST_TMPDIR", tmpdir);
+    }
+}
+
+/// The directory `test` runs in, or `None` if it runs in the directory
+/// `cli_test` was run from.
+pub fn working_dir(
+    test: Option<&super::Test>,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+) -> Option<PathBuf> {
+    match test.and_then(|test| test.cwd.as_ref()) {
+        Some(cwd) => Some(base_dir.join(cwd)),
+        None => tmpdir.map(Path::to_path_buf),
+    }
+}
+
+pub fn execute(
+    mut command: Command,
+    stdin: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+) -> io::Result<Output> {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    if stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+
+    // Commands that can time out get their own process group so that anything
+    // they spawn is killed along with them. That group is in the background,
+    // so reading the terminal would stop it; it gets no stdin instead.
+    #[cfg(unix)]
+    if timeout.is_some() {
+        if stdin.is_none() {
+            command.stdin(Stdio::null());
+        }
+
+        unsafe {
+            command.pre_exec(|| match libc::setpgid(0, 0) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            });
+        }
+    }
+
+    // A timeout too long to represent as an instant can never expire.
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            return Ok(Output {
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+                exit_code: None,
+                timed_out: None,
+                not_started: Some(spawn_error(&command, &err)),
+            })
+        }
+    };
+
+    #[cfg(unix)]
+    let _group = match timeout {
+        Some(_) => interrupt::Group::register(child.id() as libc::pid_t),
+        None => None,
+    };
+
+    // Write stdin from a separate thread so that a child producing lots of
+    // output before it finishes reading can't deadlock against us.
+    let writer = match (child.stdin.take(), stdin) {
+        (Some(mut pipe), Some(stdin)) => Some(thread::spawn(move || pipe.write_all(&stdin))),
+        _ => None,
+    };
+
+    // Each reader holds a sender and drops it once its pipe is closed, so the
+    // channel disconnects when all output has been read.
+    let (done_sender, done_receiver) = mpsc::channel::<()>();
+    let stdout_reader = read_in_background(child.stdout.take(), done_sender.clone());
+    let stderr_reader = read_in_background(child.stderr.take(), done_sender);
+
+    let status = if wait_for_output(&done_receiver, deadline) {
+        wait_for_exit(&mut child, deadline)?
+    } else {
+        None
+    };
+
+    let timed_out = match status {
+        Some(_) => None,
+        None => timeout,
+    };
+
+    let status = match status {
+        Some(status) => status,
+        None => {
+            kill(&mut child)?;
+            child.wait()?
+        }
+    };
+
+    let stdout = join_reader(stdout_reader)?;
+    let stderr = join_reader(stderr_reader)?;
+
+    if let Some(writer) = writer {
+        // The child is free to exit without consuming all of its input, so a
+        // broken pipe here isn't an error.
+        if let Ok(Err(err)) = writer.join() {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(Output {
+        stdout,
+        stderr,
+        exit_code: status.code(),
+        timed_out,
+        not_started: None,
+    })
+}
+
+/// Describes why `command` couldn't be started. A missing working directory
+/// gives the same error as a missing program, so it's checked for first.
+fn spawn_error(command: &Command, err: &io::Error) -> String {
+    let program = command.get_program().to_string_lossy();
+
+    match command.get_current_dir() {
+        Some(dir) if !dir.is_dir() => format!(
+            "Couldn't run \"{}\": the working directory \"{}\" doesn't exist",
+            program,
+            dir.display()
+        ),
+        _ => format!("Couldn't run \"{}\": {}", program, err),
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    done: mpsc::Sender<()>,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buffer)?;
+        }
+
+        drop(done);
+
+        Ok(buffer)
+    })
+}
+
+fn join_reader(reader: thread::JoinHandle<io::Result<Vec<u8>>>) -> io::Result<Vec<u8>> {
+    reader
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "reader panicked")))
+}
+
+/// Waits until both output pipes are closed. Returns `false` if the deadline
+/// passed first.
+fn wait_for_output(done: &mpsc::Receiver<()>, deadline: Option<Instant>) -> bool {
+    // Nothing is ever sent on the channel, so anything other than a timeout
+    // means that every reader has finished.
+    match deadline {
+        Some(deadline) => !matches!(
+            done.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        ),
+        None => done.recv().is_err(),
+    }
+}
+
+/// Waits for the child to exit. Returns `None` if the deadline passed first.
+fn wait_for_exit(child: &mut Child, deadline: Option<Instant>) -> io::Result<Option<ExitStatus>> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return child.wait().map(Some),
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) -> io::Result<()> {
+    match unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) } {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            // The whole group already exited on its own.
+            err if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            err => Err(err),
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) -> io::Result<()> {
+    child.kill()
+}
+
+/// Passes Ctrl-C on to the process groups of running commands. They're no
+/// longer in the terminal's foreground group, so they wouldn't get it
+/// otherwise and would be left running after `cli_test` exits.
+#[cfg(unix)]
+mod interrupt {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Once;
+
+    // Only used to initialise `GROUPS`, which is why it's interior mutable.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const FREE: AtomicI32 = AtomicI32::new(0);
+
+    /// The process groups to interrupt, with 0 in free slots. Plain atomics
+    /// are used because they're safe to read from a signal handler. Groups
+    /// beyond the last slot aren't interrupted.
+    static GROUPS: [AtomicI32; 256] = [FREE; 256];
+
+    static INSTALL: Once = Once::new();
+
+    /// A registered process group, unregistered when dropped.
+    pub struct Group(&'static AtomicI32);
+
+    impl Group {
+        pub fn register(pgid: libc::pid_t) -> Option<Group> {
+            INSTALL.call_once(|| unsafe {
+                let handler: extern "C" fn(libc::c_int) = forward;
+                libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+            });
+
+            GROUPS
+                .iter()
+                .find(|slot| {
+                    slot.compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                })
+                .map(Group)
+        }
+    }
+
+    impl Drop for Group {
+        fn drop(&mut self) {
+            self.0.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Interrupts every registered group, then lets `signal` take its
+    /// default action on `cli_test` itself.
+    extern "C" fn forward(signal: libc::c_int) {
+        for slot in GROUPS.iter() {
+            let pgid = slot.load(Ordering::SeqCst);
+            if pgid != 0 {
+                unsafe {
+                    libc::killpg(pgid, signal);
+                }
+            }
+        }
+
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
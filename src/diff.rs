@@ -0,0 +1,487 @@
+use std::ops::Range;
+
+use ansi_term::{Colour, Style};
+
+/// Unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// Past this many differences, everything is reported as changed instead of
+/// searching for the smallest diff (which takes quadratic memory).
+const MAX_EDIT_DISTANCE: isize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+impl Edit {
+    fn offset(self, offset: usize) -> Edit {
+        match self {
+            Edit::Equal(old, new) => Edit::Equal(old + offset, new + offset),
+            Edit::Delete(old) => Edit::Delete(old + offset),
+            Edit::Insert(new) => Edit::Insert(new + offset),
+        }
+    }
+}
+
+/// Renders a line-based unified diff of `expected` against `actual`, one
+/// entry per line of output.
+///
+/// Expected lines are marked with `-` and received lines with `+`. Within
+/// changed lines, the characters that differ are highlighted. Tabs, carriage
+/// returns and trailing spaces are made visible, and a missing final newline
+/// is called out.
+pub fn unified(expected: &str, actual: &str) -> Vec<String> {
+    let old: Vec<&str> = expected.split_inclusive('\n').collect();
+    let new: Vec<&str> = actual.split_inclusive('\n').collect();
+    let edits = diff(&old, &new);
+
+    let mut lines = vec![
+        Colour::Green.paint("- Expected").to_string(),
+        Colour::Red.paint("+ Received").to_string(),
+    ];
+
+    for hunk in hunks(&edits) {
+        lines.push(String::new());
+        lines.push(hunk_header(&edits, hunk.clone()));
+
+        let mut deleted: Vec<&str> = Vec::new();
+        let mut inserted: Vec<&str> = Vec::new();
+
+        for edit in &edits[hunk] {
+            match *edit {
+                Edit::Delete(old_index) => deleted.push(old[old_index]),
+                Edit::Insert(new_index) => inserted.push(new[new_index]),
+                Edit::Equal(old_index, _) => {
+                    render_changes(&deleted, &inserted, &mut lines);
+                    deleted.clear();
+                    inserted.clear();
+
+                    render_line(
+                        "  ",
+                        Style::new().dimmed(),
+                        old[old_index],
+                        None,
+                        &mut lines,
+                    );
+                }
+            }
+        }
+
+        render_changes(&deleted, &inserted, &mut lines);
+    }
+
+    lines
+}
+
+/// Groups edits into hunks: ranges of edits containing changes along with
+/// up to `CONTEXT` unchanged lines on either side.
+fn hunks(edits: &[Edit]) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+
+    for (index, edit) in edits.iter().enumerate() {
+        if let Edit::Equal(..) = edit {
+            continue;
+        }
+
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + 1 + CONTEXT).min(edits.len());
+
+        match hunks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+
+    hunks
+}
+
+fn hunk_header(edits: &[Edit], hunk: Range<usize>) -> String {
+    let is_old = |edit: &&Edit| !matches!(edit, Edit::Insert(_));
+    let is_new = |edit: &&Edit| !matches!(edit, Edit::Delete(_));
+
+    let old_start = edits[..hunk.start].iter().filter(is_old).count();
+    let new_start = edits[..hunk.start].iter().filter(is_new).count();
+    let old_len = edits[hunk.clone()].iter().filter(is_old).count();
+    let new_len = edits[hunk].iter().filter(is_new).count();
+
+    // Like `diff -u`, empty ranges start at the line before them.
+    let header = format!(
+        "@@ -{},{} +{},{} @@",
+        old_start + (old_len > 0) as usize,
+        old_len,
+        new_start + (new_len > 0) as usize,
+        new_len
+    );
+
+    Colour::Cyan.paint(header).to_string()
+}
+
+/// Renders a block of changed lines. Deleted and inserted lines are paired
+/// up so that the characters that differ between them can be highlighted.
+fn render_changes(deleted: &[&str], inserted: &[&str], lines: &mut Vec<String>) {
+    let mut deleted_highlights: Vec<Option<Vec<bool>>> = vec![None; deleted.len()];
+    let mut inserted_highlights: Vec<Option<Vec<bool>>> = vec![None; inserted.len()];
+
+    for (index, (old_line, new_line)) in deleted.iter().zip(inserted).enumerate() {
+        let old_chars: Vec<char> = old_line.trim_end_matches('\n').chars().collect();
+        let new_chars: Vec<char> = new_line.trim_end_matches('\n').chars().collect();
+        let edits = diff(&old_chars, &new_chars);
+
+        // Lines with nothing in common are already fully coloured, so there's
+        // nothing worth highlighting.
+        if !edits.iter().any(|edit| matches!(edit, Edit::Equal(..))) {
+            continue;
+        }
+
+        let mut old_changed = vec![false; old_chars.len()];
+        let mut new_changed = vec![false; new_chars.len()];
+
+        for edit in edits {
+            match edit {
+                Edit::Delete(old_index) => old_changed[old_index] = true,
+                Edit::Insert(new_index) => new_changed[new_index] = true,
+                Edit::Equal(..) => (),
+            }
+        }
+
+        deleted_highlights[index] = Some(old_changed);
+        inserted_highlights[index] = Some(new_changed);
+    }
+
+    for (line, highlights) in deleted.iter().zip(&deleted_highlights) {
+        render_line(
+            "- ",
+            Colour::Green.normal(),
+            line,
+            highlights.as_deref(),
+            lines,
+        );
+    }
+
+    for (line, highlights) in inserted.iter().zip(&inserted_highlights) {
+        render_line(
+            "+ ",
+            Colour::Red.normal(),
+            line,
+            highlights.as_deref(),
+            lines,
+        );
+    }
+}
+
+fn render_line(
+    marker: &str,
+    style: Style,
+    line: &str,
+    highlights: Option<&[bool]>,
+    lines: &mut Vec<String>,
+) {
+    let has_newline = line.ends_with('\n');
+    let chars: Vec<char> = line.trim_end_matches('\n').chars().collect();
+    let trailing_start = chars
+        .iter()
+        .rposition(|c| !matches!(c, ' ' | '\t' | '\r'))
+        .map_or(0, |index| index + 1);
+
+    let mut rendered = style.paint(marker).to_string();
+    let mut run = String::new();
+    let mut run_highlighted = false;
+
+    for (index, c) in chars.iter().enumerate() {
+        let highlighted = highlights.map_or(false, |highlights| highlights[index]);
+
+        if highlighted != run_highlighted && !run.is_empty() {
+            rendered.push_str(&paint_run(style, run_highlighted, &run));
+            run.clear();
+        }
+
+        run_highlighted = highlighted;
+        run.push(visible(*c, index >= trailing_start));
+    }
+
+    rendered.push_str(&paint_run(style, run_highlighted, &run));
+    lines.push(rendered);
+
+    if !has_newline {
+        lines.push(
+            Style::new()
+                .dimmed()
+                .paint("\\ No newline at end of output")
+                .to_string(),
+        );
+    }
+}
+
+fn paint_run(style: Style, highlighted: bool, run: &str) -> String {
+    if highlighted {
+        style.reverse().paint(run).to_string()
+    } else {
+        style.paint(run).to_string()
+    }
+}
+
+/// Maps characters that are easy to miss in a terminal to visible stand-ins.
+fn visible(c: char, is_trailing: bool) -> char {
+    match c {
+        '\t' => '→',
+        '\r' => '␍',
+        ' ' if is_trailing => '·',
+        c => c,
+    }
+}
+
+/// Computes the shortest edit script that turns `old` into `new`.
+fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|index| Edit::Equal(index, index)).collect();
+
+    edits.extend(
+        myers(old_middle, new_middle)
+            .into_iter()
+            .map(|edit| edit.offset(prefix)),
+    );
+
+    edits.extend(
+        (0..suffix)
+            .map(|index| Edit::Equal(old.len() - suffix + index, new.len() - suffix + index)),
+    );
+
+    edits
+}
+
+/// Myers' O((N+M)D) diff algorithm.
+fn myers<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let limit = (n + m).min(MAX_EDIT_DISTANCE);
+    let offset = limit + 1;
+
+    // `v[offset + k]` is the furthest x reached on diagonal k.
+    let mut v = vec![0isize; (2 * limit + 3) as usize];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=limit {
+        // Round d only reads diagonals -d-1 through d+1, so that's all that
+        // needs to be kept for backtracking.
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index] = x;
+
+            if x >= n && y >= m {
+                return backtrack(&trace, n, m);
+            }
+        }
+    }
+
+    (0..old.len())
+        .map(Edit::Delete)
+        .chain((0..new.len()).map(Edit::Insert))
+        .collect()
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut x = n;
+    let mut y = m;
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert(previous_y as usize));
+            } else {
+                edits.push(Edit::Delete(previous_x as usize));
+            }
+        }
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `edits` turns `old` into `new`, and returns how many
+    /// lines were changed.
+    fn check<T: PartialEq + std::fmt::Debug>(old: &[T], new: &[T], edits: &[Edit]) -> usize {
+        let mut old_index = 0;
+        let mut new_index = 0;
+
+        for edit in edits {
+            match *edit {
+                Edit::Equal(o, n) => {
+                    assert_eq!((o, n), (old_index, new_index));
+                    assert_eq!(old[o], new[n]);
+                    old_index += 1;
+                    new_index += 1;
+                }
+                Edit::Delete(o) => {
+                    assert_eq!(o, old_index);
+                    old_index += 1;
+                }
+                Edit::Insert(n) => {
+                    assert_eq!(n, new_index);
+                    new_index += 1;
+                }
+            }
+        }
+
+        assert_eq!((old_index, new_index), (old.len(), new.len()));
+
+        edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count()
+    }
+
+    fn header(header: &str) -> String {
+        Colour::Cyan.paint(header).to_string()
+    }
+
+    #[test]
+    fn myers_of_equal_input() {
+        let edits = myers(&['a', 'b'], &['a', 'b']);
+
+        assert_eq!(edits, vec![Edit::Equal(0, 0), Edit::Equal(1, 1)]);
+    }
+
+    #[test]
+    fn myers_of_empty_input() {
+        assert_eq!(myers::<char>(&[], &[]), vec![]);
+        assert_eq!(myers(&['a'], &[]), vec![Edit::Delete(0)]);
+        assert_eq!(myers(&[], &['a']), vec![Edit::Insert(0)]);
+    }
+
+    #[test]
+    fn myers_finds_the_shortest_edit_script() {
+        let old: Vec<char> = "abcabba".chars().collect();
+        let new: Vec<char> = "cbabac".chars().collect();
+
+        assert_eq!(check(&old, &new, &myers(&old, &new)), 5);
+    }
+
+    #[test]
+    fn myers_keeps_common_lines_between_changes() {
+        let old = ["a", "x", "b", "y", "c"];
+        let new = ["a", "b", "z", "c"];
+
+        assert_eq!(check(&old, &new, &myers(&old, &new)), 3);
+    }
+
+    #[test]
+    fn myers_gives_up_past_the_maximum_edit_distance() {
+        let old: Vec<usize> = (0..600).collect();
+        let new: Vec<usize> = (600..1200).collect();
+        let edits = myers(&old, &new);
+
+        assert_eq!(check(&old, &new, &edits), 1200);
+        assert!(edits[..600]
+            .iter()
+            .all(|edit| matches!(edit, Edit::Delete(_))));
+    }
+
+    #[test]
+    fn myers_searches_up_to_the_maximum_edit_distance() {
+        let old: Vec<usize> = (0..1000).map(|n| n % 2).collect();
+        let new: Vec<usize> = (0..1000)
+            .map(|n| if n % 4 == 0 { 2 } else { n % 2 })
+            .collect();
+
+        assert_eq!(check(&old, &new, &myers(&old, &new)), 500);
+    }
+
+    #[test]
+    fn hunk_includes_context() {
+        let old: Vec<String> = (1..=10).map(|n| format!("{}\n", n)).collect();
+        let mut new = old.clone();
+        new[4] = String::from("five\n");
+        let edits = diff(&old, &new);
+        let hunks = hunks(&edits);
+
+        assert_eq!(hunks, vec![1..9]);
+        assert_eq!(
+            hunk_header(&edits, hunks[0].clone()),
+            header("@@ -2,7 +2,7 @@")
+        );
+    }
+
+    #[test]
+    fn distant_changes_are_separate_hunks() {
+        let old: Vec<String> = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let mut new = old.clone();
+        new.remove(1);
+        new.insert(16, String::from("new\n"));
+        let edits = diff(&old, &new);
+        let hunks = hunks(&edits);
+
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(
+            hunk_header(&edits, hunks[0].clone()),
+            header("@@ -1,5 +1,4 @@")
+        );
+        assert_eq!(
+            hunk_header(&edits, hunks[1].clone()),
+            header("@@ -15,6 +14,7 @@")
+        );
+    }
+
+    #[test]
+    fn empty_ranges_start_at_the_line_before() {
+        let edits = diff(&["a\n"], &[]);
+
+        assert_eq!(hunk_header(&edits, 0..1), header("@@ -1,1 +0,0 @@"));
+
+        let edits = diff(&[], &["a\n"]);
+
+        assert_eq!(hunk_header(&edits, 0..1), header("@@ -0,0 +1,1 @@"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::CliError;
+
+/// Expands the paths given on the command line into a list of test files.
+///
+/// Files are used as they are. Directories are searched recursively for
+/// `.yml` and `.yaml` files, skipping hidden files and directories. Paths
+/// that don't exist but contain `*`, `?` or `[` are treated as glob
+/// patterns. Each file is only included once, in the order it was first
+/// found.
+pub fn test_files(paths: &[&str]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+
+    for &path in paths {
+        let found = find(path)?;
+
+        if found.is_empty() {
+            return Err(CliError::NoTestFiles(path.to_string()));
+        }
+
+        for file in found {
+            if seen.insert(file.clone()) {
+                files.push(file);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn find(path: &str) -> Result<Vec<PathBuf>, CliError> {
+    let is_pattern = path.contains(&['*', '?', '['][..]);
+
+    if is_pattern && !Path::new(path).exists() {
+        let mut files: Vec<PathBuf> = Vec::new();
+
+        for entry in glob::glob(path)? {
+            let entry = entry.map_err(glob::GlobError::into_error)?;
+
+            if entry.is_dir() {
+                search_dir(&entry, &mut files)?;
+            } else {
+                files.push(entry);
+            }
+        }
+
+        return Ok(files);
+    }
+
+    let path = PathBuf::from(path);
+
+    if path.is_dir() {
+        let mut files: Vec<PathBuf> = Vec::new();
+        search_dir(&path, &mut files)?;
+
+        Ok(files)
+    } else {
+        // Missing files are left for reading the file to report.
+        Ok(vec![path])
+    }
+}
+
+fn search_dir(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+
+    entries.sort();
+
+    for entry in entries {
+        if is_hidden(&entry) {
+            continue;
+        }
+
+        if entry.is_dir() {
+            search_dir(&entry, files)?;
+        } else if is_test_file(&entry) {
+            files.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| name.starts_with('.'))
+}
+
+fn is_test_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("yml") | Some("yaml")
+    )
+}
@@ -1,9 +1,21 @@
+use std::error;
 use std::fmt;
 use std::io;
 use std::string;
 
+#[derive(Debug)]
 pub enum ValidationError {
     DuplicateTestName(String),
+    ConflictingStdin(String),
+    EmptyCommand(String),
+    ConflictingCwd(String),
+    /// Both `out` and `out_file`, or `err` and `err_file`, by the name of
+    /// the first.
+    ConflictingOutput(String, &'static str),
+    InvalidTimeout(String),
+    InvalidPattern(String, regex::Error),
+    InvalidTag(String, String),
+    InvalidFilePath(String, String),
 }
 
 impl fmt::Display for ValidationError {
@@ -16,28 +28,109 @@ impl fmt::Display for ValidationError {
                     name
                 )
             }
+            ValidationError::ConflictingStdin(ref name) => {
+                write!(
+                    f,
+                    "Test \"{}\" sets both stdin and stdin_file. Only one may be given.",
+                    name
+                )
+            }
+            ValidationError::EmptyCommand(ref name) => {
+                write!(
+                    f,
+                    "Test \"{}\" has an empty list for in. It must at least name the program to run.",
+                    name
+                )
+            }
+            ValidationError::ConflictingCwd(ref name) => {
+                write!(
+                    f,
+                    "Test \"{}\" sets both cwd and tmpdir. Only one may be given.",
+                    name
+                )
+            }
+            ValidationError::ConflictingOutput(ref name, key) => {
+                write!(
+                    f,
+                    "Test \"{}\" sets both {} and {}_file. Only one may be given.",
+                    name, key, key
+                )
+            }
+            ValidationError::InvalidTimeout(ref name) => {
+                write!(
+                    f,
+                    "Test \"{}\" has an invalid timeout. Timeouts must be a non-negative number of seconds.",
+                    name
+                )
+            }
+            ValidationError::InvalidPattern(ref name, ref err) => {
+                write!(f, "Test \"{}\" has an invalid pattern: {}", name, err)
+            }
+            ValidationError::InvalidTag(ref name, ref tag) => {
+                write!(
+                    f,
+                    "Test \"{}\" has an invalid tag \"{}\". Tags may only contain letters, digits, '-', '_', '.', ':' and '/', and can't be \"and\", \"or\" or \"not\".",
+                    name, tag
+                )
+            }
+            ValidationError::InvalidFilePath(ref name, ref path) => {
+                write!(
+                    f,
+                    "Test \"{}\" has an invalid file path \"{}\". Paths in files must be relative and can't contain \"..\".",
+                    name, path
+                )
+            }
         }
     }
 }
 
+#[derive(Debug)]
 pub enum CliError {
     Io(io::Error),
     Yaml(serde_yaml::Error),
     Utf8(string::FromUtf8Error),
+    Regex(regex::Error),
+    Glob(glob::PatternError),
     Validation(ValidationError),
+    NoTestFiles(String),
+    /// A file that a test refers to couldn't be read or written, by the name
+    /// of the test and the path as it's written in the test.
+    TestFile(String, String, io::Error),
+    /// An error that happened while running one of several test files.
+    InFile(String, Box<CliError>),
 }
 
 impl fmt::Display for CliError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Error: ")?;
+        self.fmt_cause(f)
+    }
+}
 
+impl error::Error for ValidationError {}
+
+impl error::Error for CliError {}
+
+impl CliError {
+    fn fmt_cause(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            CliError::Io(ref err) => err.fmt(f),
-            CliError::Yaml(ref err) => err.fmt(f),
-            CliError::Utf8(ref err) => err.fmt(f),
+            CliError::Io(ref err) => write!(f, "{}", err),
+            CliError::Yaml(ref err) => write!(f, "{}", err),
+            CliError::Utf8(ref err) => write!(f, "{}", err),
+            CliError::Regex(ref err) => write!(f, "{}", err),
+            CliError::Glob(ref err) => write!(f, "invalid glob pattern: {}", err),
             CliError::Validation(ref err) => {
-                write!(f, "validation error: ")?;
-                err.fmt(f)
+                write!(f, "validation error: {}", err)
+            }
+            CliError::NoTestFiles(ref path) => {
+                write!(f, "no test files found at \"{}\"", path)
+            }
+            CliError::TestFile(ref name, ref path, ref err) => {
+                write!(f, "test \"{}\" couldn't use \"{}\": {}", name, path, err)
+            }
+            CliError::InFile(ref file, ref err) => {
+                write!(f, "{}: ", file)?;
+                err.fmt_cause(f)
             }
         }
     }
@@ -60,3 +153,15 @@ impl From<string::FromUtf8Error> for CliError {
         CliError::Utf8(err)
     }
 }
+
+impl From<glob::PatternError> for CliError {
+    fn from(err: glob::PatternError) -> CliError {
+        CliError::Glob(err)
+    }
+}
+
+impl From<regex::Error> for CliError {
+    fn from(err: regex::Error) -> CliError {
+        CliError::Regex(err)
+    }
+}
@@ -1,8 +1,248 @@
 use std::fmt;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use ansi_term::Colour;
+use regex::Regex;
+use serde::de::{self, value::MapAccessDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
 
+use crate::command;
+use crate::diff;
 use crate::errors;
+use crate::tree::{self, TreeChange};
+use crate::update;
+
+/// What a test expects to see on stdout or stderr.
+#[derive(Clone, Debug)]
+pub enum OutputExpectation {
+    /// The output must be exactly this string.
+    Exact(String),
+    /// The output must satisfy each of the given matchers.
+    Matching(OutputMatchers),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputMatchers {
+    /// A regular expression that must match somewhere in the output.
+    pub matches: Option<String>,
+    /// Fragments that must each appear somewhere in the output.
+    #[serde(default)]
+    pub contains: Vec<String>,
+    /// Fragments that must not appear anywhere in the output.
+    #[serde(default)]
+    pub not_contains: Vec<String>,
+}
+
+impl OutputMatchers {
+    fn patterns(&self) -> Vec<&str> {
+        self.matches.iter().map(String::as_str).collect()
+    }
+}
+
+impl OutputExpectation {
+    /// The regular expressions used by this expectation, if any.
+    pub fn patterns(&self) -> Vec<&str> {
+        match *self {
+            OutputExpectation::Exact(_) => Vec::new(),
+            OutputExpectation::Matching(ref matchers) => matchers.patterns(),
+        }
+    }
+}
+
+// Any scalar is exact output, so `out: 42` and `err: true` work without
+// quoting, which an untagged enum wouldn't allow.
+impl<'de> Deserialize<'de> for OutputExpectation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(TextOrVisitor(PhantomData))? {
+            TextOr::Text(text) => Ok(OutputExpectation::Exact(text)),
+            TextOr::Matchers(matchers) => Ok(OutputExpectation::Matching(matchers)),
+        }
+    }
+}
+
+/// What a test expects of a file in its working directory once it has run.
+#[derive(Clone, Debug)]
+pub enum FileExpectation {
+    /// The file must exist with exactly these contents.
+    Exact(String),
+    /// The file must exist (or not) and its contents must satisfy each of
+    /// the given matchers.
+    Matching(FileMatchers),
+}
+
+#[derive(Clone, Debug)]
+pub struct FileMatchers {
+    /// Whether the file must exist. The other matchers only apply when it
+    /// does.
+    pub exists: bool,
+    pub contents: OutputMatchers,
+}
+
+impl FileExpectation {
+    pub fn patterns(&self) -> Vec<&str> {
+        match *self {
+            FileExpectation::Exact(_) => Vec::new(),
+            FileExpectation::Matching(ref matchers) => matchers
+                .contents
+                .matches
+                .iter()
+                .map(String::as_str)
+                .collect(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileExpectation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(TextOrVisitor(PhantomData))? {
+            TextOr::Text(text) => Ok(FileExpectation::Exact(text)),
+            TextOr::Matchers(matchers) => Ok(FileExpectation::Matching(matchers)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileMatchers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut mapping = match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::Mapping(mapping) => mapping,
+            _ => return Err(de::Error::custom("expected a map of matchers")),
+        };
+
+        let exists = match mapping.remove(&serde_yaml::Value::String(String::from("exists"))) {
+            Some(serde_yaml::Value::Bool(exists)) => exists,
+            Some(_) => return Err(de::Error::custom("exists must be true or false")),
+            None => true,
+        };
+        let contents = serde_yaml::from_value(serde_yaml::Value::Mapping(mapping))
+            .map_err(de::Error::custom)?;
+
+        Ok(FileMatchers { exists, contents })
+    }
+}
+
+enum TextOr<M> {
+    Text(String),
+    Matchers(M),
+}
+
+struct TextOrVisitor<M>(PhantomData<M>);
+
+impl<'de, M: Deserialize<'de>> Visitor<'de> for TextOrVisitor<M> {
+    type Value = TextOr<M>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the expected text, or a map of matchers")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value.to_string()))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+        Ok(TextOr::Text(value))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        M::deserialize(MapAccessDeserializer::new(map)).map(TextOr::Matchers)
+    }
+}
+
+/// What a test expects of a directory it produces: that it holds the same
+/// files as a golden directory.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TreeExpectation {
+    /// The directory the command produces, relative to its working
+    /// directory.
+    pub path: String,
+    /// The directory to compare it with, relative to the test file.
+    pub golden: String,
+    /// Whether files' permissions must match too.
+    #[serde(default)]
+    pub permissions: bool,
+}
+
+/// Where checked output came from.
+#[derive(Clone, Debug)]
+pub enum Stream {
+    StdOut,
+    StdErr,
+    /// A file written by the command, by its path relative to the test's
+    /// working directory.
+    File(String),
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stream::StdOut => write!(f, "Output on stdout"),
+            Stream::StdErr => write!(f, "Output on stderr"),
+            Stream::File(ref path) => write!(f, "File \"{}\"", path),
+        }
+    }
+}
+
+impl Stream {
+    /// Identifies the stream, or the file, for machine-readable reports.
+    fn detail(&self) -> (&'static str, Detail) {
+        match *self {
+            Stream::StdOut => ("stream", Detail::Text(String::from("stdout"))),
+            Stream::StdErr => ("stream", Detail::Text(String::from("stderr"))),
+            Stream::File(ref path) => ("path", Detail::Text(path.clone())),
+        }
+    }
+}
+
+/// A value describing a failure, for machine-readable reports.
+pub enum Detail {
+    Text(String),
+    Integer(i64),
+    Seconds(f64),
+}
+
+/// A failed expectation or hook, as reporters describe it.
+pub trait Failure {
+    /// A short, stable name for the kind of failure.
+    fn kind(&self) -> &'static str;
+
+    /// The values involved in the failure, by name.
+    fn details(&self) -> Vec<(&'static str, Detail)>;
+
+    /// The failure report without colours, for writing to files and other
+    /// tools. `diff` shows unexpected output as a diff.
+    fn plain_report(&self, diff: bool) -> String;
+
+    /// The first line of the report.
+    fn summary(&self) -> String {
+        self.plain_report(false)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
 
 pub struct Expectation<T> {
     expected: T,
@@ -12,13 +252,258 @@ pub struct Expectation<T> {
 pub enum FailedExpectation {
     StdOut(Expectation<String>),
     StdErr(Expectation<String>),
+    /// Output compared with a golden file, by its path relative to the test
+    /// file.
+    StdOutFile(String, Expectation<String>),
+    StdErrFile(String, Expectation<String>),
+    MissingGoldenFile {
+        stream: Stream,
+        golden: String,
+        actual: String,
+    },
     ExitCode(Expectation<i32>),
     MissingExitCode,
+    /// A file's contents, by its path.
+    File(String, Expectation<String>),
+    MissingFile(String),
+    UnexpectedFile(String),
+    /// A file that differs between a directory the test produced and its
+    /// golden directory, by its path within them.
+    Tree {
+        path: String,
+        golden: String,
+        file: String,
+        change: TreeChange,
+    },
+    PatternMismatch {
+        stream: Stream,
+        pattern: String,
+        actual: String,
+    },
+    MissingFragment {
+        stream: Stream,
+        fragment: String,
+        actual: String,
+    },
+    UnexpectedFragment {
+        stream: Stream,
+        fragment: String,
+        actual: String,
+    },
+    TimedOut {
+        timeout: Duration,
+        stdout: String,
+        stderr: String,
+    },
+    /// The command couldn't be started, with the reason.
+    NotStarted(String),
 }
 
 impl fmt::Display for FailedExpectation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_report(f, true)
+    }
+}
+
+impl Failure for FailedExpectation {
+    fn plain_report(&self, diff: bool) -> String {
+        let report = Report {
+            expectation: self,
+            diff,
+        };
+
+        strip_colours(&report.to_string())
+    }
+
+    fn kind(&self) -> &'static str {
         match *self {
+            FailedExpectation::StdOut(_)
+            | FailedExpectation::StdErr(_)
+            | FailedExpectation::StdOutFile(..)
+            | FailedExpectation::StdErrFile(..) => "output",
+            FailedExpectation::MissingGoldenFile { .. } => "missing_golden_file",
+            FailedExpectation::ExitCode(_) => "exit_code",
+            FailedExpectation::MissingExitCode => "missing_exit_code",
+            FailedExpectation::File(..) => "file",
+            FailedExpectation::MissingFile(_) => "missing_file",
+            FailedExpectation::UnexpectedFile(_) => "unexpected_file",
+            FailedExpectation::Tree { ref change, .. } => match *change {
+                TreeChange::Added { .. } => "unexpected_tree_file",
+                TreeChange::Removed => "missing_tree_file",
+                TreeChange::Changed { .. } => "tree_file",
+                TreeChange::Mode { .. } => "tree_file_mode",
+            },
+            FailedExpectation::PatternMismatch { .. } => "pattern",
+            FailedExpectation::MissingFragment { .. } => "missing_fragment",
+            FailedExpectation::UnexpectedFragment { .. } => "unexpected_fragment",
+            FailedExpectation::TimedOut { .. } => "timeout",
+            FailedExpectation::NotStarted(_) => "not_started",
+        }
+    }
+
+    fn details(&self) -> Vec<(&'static str, Detail)> {
+        let text = |value: &str| Detail::Text(value.to_string());
+
+        match *self {
+            FailedExpectation::StdOut(ref expectation) => vec![
+                ("stream", text("stdout")),
+                ("expected", text(&expectation.expected)),
+                ("actual", text(&expectation.actual)),
+            ],
+            FailedExpectation::StdErr(ref expectation) => vec![
+                ("stream", text("stderr")),
+                ("expected", text(&expectation.expected)),
+                ("actual", text(&expectation.actual)),
+            ],
+            FailedExpectation::StdOutFile(ref golden, ref expectation) => vec![
+                ("stream", text("stdout")),
+                ("golden", text(golden)),
+                ("expected", text(&expectation.expected)),
+                ("actual", text(&expectation.actual)),
+            ],
+            FailedExpectation::StdErrFile(ref golden, ref expectation) => vec![
+                ("stream", text("stderr")),
+                ("golden", text(golden)),
+                ("expected", text(&expectation.expected)),
+                ("actual", text(&expectation.actual)),
+            ],
+            FailedExpectation::MissingGoldenFile {
+                ref stream,
+                ref golden,
+                ref actual,
+            } => vec![
+                stream.detail(),
+                ("golden", text(golden)),
+                ("actual", text(actual)),
+            ],
+            FailedExpectation::ExitCode(ref expectation) => vec![
+                ("expected", Detail::Integer(expectation.expected.into())),
+                ("actual", Detail::Integer(expectation.actual.into())),
+            ],
+            FailedExpectation::MissingExitCode => Vec::new(),
+            FailedExpectation::File(ref path, ref expectation) => vec![
+                ("path", text(path)),
+                ("expected", text(&expectation.expected)),
+                ("actual", text(&expectation.actual)),
+            ],
+            FailedExpectation::MissingFile(ref path)
+            | FailedExpectation::UnexpectedFile(ref path) => vec![("path", text(path))],
+            FailedExpectation::Tree {
+                ref path,
+                ref golden,
+                ref file,
+                ref change,
+            } => {
+                let mut details = vec![
+                    ("path", text(&format!("{}/{}", path, file))),
+                    ("golden", text(&format!("{}/{}", golden, file))),
+                ];
+
+                match *change {
+                    TreeChange::Changed {
+                        ref expected,
+                        ref actual,
+                    } => {
+                        details.push(("expected", text(&String::from_utf8_lossy(expected))));
+                        details.push(("actual", text(&String::from_utf8_lossy(actual))));
+                    }
+                    TreeChange::Mode { expected, actual } => {
+                        details.push(("expected", text(&format!("{:o}", expected))));
+                        details.push(("actual", text(&format!("{:o}", actual))));
+                    }
+                    TreeChange::Added { .. } | TreeChange::Removed => (),
+                }
+
+                details
+            }
+            FailedExpectation::PatternMismatch {
+                ref stream,
+                ref pattern,
+                ref actual,
+            } => vec![
+                stream.detail(),
+                ("pattern", text(pattern)),
+                ("actual", text(actual)),
+            ],
+            FailedExpectation::MissingFragment {
+                ref stream,
+                ref fragment,
+                ref actual,
+            }
+            | FailedExpectation::UnexpectedFragment {
+                ref stream,
+                ref fragment,
+                ref actual,
+            } => vec![
+                stream.detail(),
+                ("fragment", text(fragment)),
+                ("actual", text(actual)),
+            ],
+            FailedExpectation::TimedOut {
+                timeout,
+                ref stdout,
+                ref stderr,
+            } => vec![
+                ("timeout", Detail::Seconds(timeout.as_secs_f64())),
+                ("stdout", text(stdout)),
+                ("stderr", text(stderr)),
+            ],
+            FailedExpectation::NotStarted(ref reason) => vec![("reason", text(reason))],
+        }
+    }
+}
+
+impl FailedExpectation {
+    /// Writes the failure report. Unexpected output is shown as a diff
+    /// against the expected output when `diff` is true, and in full
+    /// otherwise.
+    pub fn fmt_report(&self, f: &mut fmt::Formatter, diff: bool) -> fmt::Result {
+        match *self {
+            FailedExpectation::StdOut(ref expectation) if diff => {
+                write!(f, "    Unexpected output on stdout.\n\n")?;
+                write_diff(f, expectation)
+            }
+            FailedExpectation::StdErr(ref expectation) if diff => {
+                write!(f, "    Unexpected output on stderr.\n\n")?;
+                write_diff(f, expectation)
+            }
+            FailedExpectation::StdOutFile(ref golden, ref expectation) => {
+                write!(
+                    f,
+                    "    Unexpected output on stdout, compared with \"{}\".\n\n",
+                    golden
+                )?;
+                write_comparison(f, expectation, diff)
+            }
+            FailedExpectation::StdErrFile(ref golden, ref expectation) => {
+                write!(
+                    f,
+                    "    Unexpected output on stderr, compared with \"{}\".\n\n",
+                    golden
+                )?;
+                write_comparison(f, expectation, diff)
+            }
+            FailedExpectation::MissingGoldenFile {
+                ref stream,
+                ref golden,
+                ref actual,
+            } => {
+                write!(
+                    f,
+                    "    {} can't be compared with \"{}\", which doesn't exist.\n\
+                    \n\
+                    \x20   Received:\n\
+                    \n\
+                    \x20     {}\n",
+                    stream,
+                    golden,
+                    Colour::Red.paint(actual)
+                )
+            }
+            FailedExpectation::File(ref path, ref expectation) if diff => {
+                write!(f, "    Unexpected contents in file \"{}\".\n\n", path)?;
+                write_diff(f, expectation)
+            }
             FailedExpectation::StdOut(ref expectation) => {
                 write!(
                     f,
@@ -63,62 +548,580 @@ impl fmt::Display for FailedExpectation {
                     Colour::Red.paint(expectation.actual.to_string())
                 )
             }
+            FailedExpectation::File(ref path, ref expectation) => {
+                write!(
+                    f,
+                    "    Unexpected contents in file \"{}\".\n\
+                    \n\
+                    \x20   Expected:\n\
+                    \n\
+                    \x20     {}\n\
+                    \n\
+                    \x20   Received:\n\
+                    \n\
+                    \x20     {}\n",
+                    path,
+                    Colour::Green.paint(&expectation.expected),
+                    Colour::Red.paint(&expectation.actual)
+                )
+            }
             FailedExpectation::MissingExitCode => {
                 write!(f, "    No exit code received.")
             }
+            FailedExpectation::Tree {
+                ref path,
+                ref golden,
+                ref file,
+                ref change,
+            } => match *change {
+                TreeChange::Added { .. } => write!(
+                    f,
+                    "    Unexpected file \"{}/{}\", which isn't in \"{}\".\n\n",
+                    path, file, golden
+                ),
+                TreeChange::Removed => write!(
+                    f,
+                    "    Missing file \"{}/{}\", which is in \"{}\".\n\n",
+                    path, file, golden
+                ),
+                TreeChange::Changed {
+                    ref expected,
+                    ref actual,
+                } => {
+                    let expectation = Expectation {
+                        expected: String::from_utf8_lossy(expected).into_owned(),
+                        actual: String::from_utf8_lossy(actual).into_owned(),
+                    };
+
+                    write!(
+                        f,
+                        "    Unexpected contents in file \"{}/{}\", compared with \"{}/{}\".\n\n",
+                        path, file, golden, file
+                    )?;
+                    write_comparison(f, &expectation, diff)
+                }
+                TreeChange::Mode { expected, actual } => write!(
+                    f,
+                    "    Unexpected permissions on file \"{}/{}\".\n\
+                    \n\
+                    \x20   Expected: {}\n\
+                    \n\
+                    \x20   Received: {}\n\n",
+                    path,
+                    file,
+                    Colour::Green.paint(format!("{:o}", expected)),
+                    Colour::Red.paint(format!("{:o}", actual))
+                ),
+            },
+            FailedExpectation::MissingFile(ref path) => {
+                write!(
+                    f,
+                    "    Expected file \"{}\" to exist, but it doesn't.\n\n",
+                    path
+                )
+            }
+            FailedExpectation::UnexpectedFile(ref path) => {
+                write!(
+                    f,
+                    "    Expected file \"{}\" not to exist, but it does.\n\n",
+                    path
+                )
+            }
+            FailedExpectation::PatternMismatch {
+                ref stream,
+                ref pattern,
+                ref actual,
+            } => {
+                write!(
+                    f,
+                    "    {} doesn't match the pattern.\n\
+                    \n\
+                    \x20   Pattern:\n\
+                    \n\
+                    \x20     {}\n\
+                    \n\
+                    \x20   Received:\n\
+                    \n\
+                    \x20     {}\n",
+                    stream,
+                    Colour::Green.paint(pattern),
+                    Colour::Red.paint(actual)
+                )
+            }
+            FailedExpectation::MissingFragment {
+                ref stream,
+                ref fragment,
+                ref actual,
+            } => {
+                write!(
+                    f,
+                    "    {} doesn't contain an expected fragment.\n\
+                    \n\
+                    \x20   Expected to contain:\n\
+                    \n\
+                    \x20     {}\n\
+                    \n\
+                    \x20   Received:\n\
+                    \n\
+                    \x20     {}\n",
+                    stream,
+                    Colour::Green.paint(fragment),
+                    Colour::Red.paint(actual)
+                )
+            }
+            FailedExpectation::UnexpectedFragment {
+                ref stream,
+                ref fragment,
+                ref actual,
+            } => {
+                write!(
+                    f,
+                    "    {} contains an unexpected fragment.\n\
+                    \n\
+                    \x20   Expected not to contain:\n\
+                    \n\
+                    \x20     {}\n\
+                    \n\
+                    \x20   Received:\n\
+                    \n\
+                    \x20     {}\n",
+                    stream,
+                    Colour::Green.paint(fragment),
+                    Colour::Red.paint(actual)
+                )
+            }
+            FailedExpectation::TimedOut {
+                timeout,
+                ref stdout,
+                ref stderr,
+            } => {
+                write!(
+                    f,
+                    "    Timed out after {:?}.\n\
+                    \n\
+                    \x20   Received on stdout:\n\
+                    \n\
+                    \x20     {}\n\
+                    \n\
+                    \x20   Received on stderr:\n\
+                    \n\
+                    \x20     {}\n",
+                    timeout,
+                    Colour::Red.paint(stdout),
+                    Colour::Red.paint(stderr)
+                )
+            }
+            FailedExpectation::NotStarted(ref reason) => {
+                write!(f, "    {}.\n\n", Colour::Red.paint(reason))
+            }
+        }
+    }
+}
+
+struct Report<'a> {
+    expectation: &'a FailedExpectation,
+    diff: bool,
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.expectation.fmt_report(f, self.diff)
+    }
+}
+
+fn strip_colours(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Skip the rest of the escape sequence, which ends with a letter.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+
+    plain
+}
+
+fn write_diff(f: &mut fmt::Formatter, expectation: &Expectation<String>) -> fmt::Result {
+    for line in diff::unified(&expectation.expected, &expectation.actual) {
+        writeln!(f, "      {}", line)?;
+    }
+
+    writeln!(f)
+}
+
+/// Writes the expected and received output, as a diff when `diff` is true
+/// and in full otherwise.
+fn write_comparison(
+    f: &mut fmt::Formatter,
+    expectation: &Expectation<String>,
+    diff: bool,
+) -> fmt::Result {
+    if diff {
+        write_diff(f, expectation)
+    } else {
+        write!(
+            f,
+            "    Expected:\n\
+            \n\
+            \x20     {}\n\
+            \n\
+            \x20   Received:\n\
+            \n\
+            \x20     {}\n",
+            Colour::Green.paint(&expectation.expected),
+            Colour::Red.paint(&expectation.actual)
+        )
+    }
+}
+
+/// Builds the update that would make a failed test pass, if all of its
+/// failures are ones that update mode can fix.
+pub fn update_for(
+    test: &super::Test,
+    failed_expectations: &[FailedExpectation],
+) -> Option<update::Update> {
+    let mut update = update::Update {
+        name: test.name.clone(),
+        out: None,
+        err: None,
+        exit_code: None,
+        files: Vec::new(),
+    };
+
+    for failed_expectation in failed_expectations {
+        match *failed_expectation {
+            FailedExpectation::StdOut(ref expectation) => {
+                update.out = Some(expectation.actual.clone())
+            }
+            FailedExpectation::StdErr(ref expectation) => {
+                update.err = Some(expectation.actual.clone())
+            }
+            FailedExpectation::ExitCode(ref expectation) => {
+                update.exit_code = Some(expectation.actual)
+            }
+            FailedExpectation::StdOutFile(ref golden, ref expectation)
+            | FailedExpectation::StdErrFile(ref golden, ref expectation) => {
+                update.files.push(update::FileUpdate::Write(
+                    PathBuf::from(golden),
+                    expectation.actual.clone().into_bytes(),
+                ))
+            }
+            FailedExpectation::MissingGoldenFile {
+                ref golden,
+                ref actual,
+                ..
+            } => update.files.push(update::FileUpdate::Write(
+                PathBuf::from(golden),
+                actual.clone().into_bytes(),
+            )),
+            FailedExpectation::Tree {
+                ref golden,
+                ref file,
+                ref change,
+                ..
+            } => {
+                let path = Path::new(golden).join(file);
+
+                match *change {
+                    TreeChange::Added { ref contents, mode } => {
+                        update
+                            .files
+                            .push(update::FileUpdate::Write(path.clone(), contents.clone()));
+
+                        if let Some(mode) = mode {
+                            update.files.push(update::FileUpdate::SetMode(path, mode));
+                        }
+                    }
+                    TreeChange::Removed => update.files.push(update::FileUpdate::Remove(path)),
+                    TreeChange::Changed { ref actual, .. } => update
+                        .files
+                        .push(update::FileUpdate::Write(path, actual.clone())),
+                    TreeChange::Mode { actual, .. } => {
+                        update.files.push(update::FileUpdate::SetMode(path, actual))
+                    }
+                }
+            }
+            _ => return None,
         }
     }
+
+    Some(update)
 }
 
+/// Checks `output`, and the files the test expects in `dir`, its working
+/// directory. Golden directories are relative to `base_dir`.
 pub fn verify_expectations(
     test: &super::Test,
-    output: std::process::Output,
+    output: &command::Output,
+    base_dir: &Path,
+    dir: &Path,
 ) -> Result<Vec<FailedExpectation>, errors::CliError> {
     let mut failed_expectations: Vec<FailedExpectation> = Vec::new();
 
-    let stdout = String::from_utf8(output.stdout)?;
-    let stderr = String::from_utf8(output.stderr)?;
-    let exit_code = output.status.code();
+    if let Some(reason) = &output.not_started {
+        failed_expectations.push(FailedExpectation::NotStarted(reason.clone()));
 
-    if let Some(failed_expectation) = verify_stdout(test, &stdout) {
-        failed_expectations.push(failed_expectation);
+        return Ok(failed_expectations);
     }
 
-    if let Some(failed_expectation) = verify_stderr(test, &stderr) {
-        failed_expectations.push(failed_expectation);
+    // A killed command's output is cut off at an arbitrary point, so the
+    // other expectations aren't meaningful (and it may not even be valid
+    // UTF-8).
+    if let Some(timeout) = output.timed_out {
+        failed_expectations.push(FailedExpectation::TimedOut {
+            timeout,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+
+        return Ok(failed_expectations);
     }
 
+    let stdout = String::from_utf8(output.stdout.clone())?;
+    let stderr = String::from_utf8(output.stderr.clone())?;
+    let exit_code = output.exit_code;
+
+    failed_expectations.extend(verify_stdout(test, &stdout, base_dir)?);
+    failed_expectations.extend(verify_stderr(test, &stderr, base_dir)?);
+
     if let Some(failed_expectation) = verify_exit_code(test, exit_code) {
         failed_expectations.push(failed_expectation);
     }
 
+    failed_expectations.extend(verify_files(test, dir)?);
+
+    if let Some(tree) = &test.expect_tree {
+        failed_expectations.extend(verify_tree(tree, base_dir, dir)?);
+    }
+
     Ok(failed_expectations)
 }
 
-fn verify_stdout(test: &super::Test, stdout: &str) -> Option<FailedExpectation> {
+fn verify_stdout(
+    test: &super::Test,
+    stdout: &str,
+    base_dir: &Path,
+) -> Result<Vec<FailedExpectation>, errors::CliError> {
+    if let Some(out_file) = &test.out_file {
+        return Ok(match read_golden(test, out_file, base_dir)? {
+            Some(expected_out) if stdout.ne(&expected_out) => vec![FailedExpectation::StdOutFile(
+                out_file.to_string(),
+                Expectation {
+                    actual: stdout.to_string(),
+                    expected: expected_out,
+                },
+            )],
+            Some(_) => Vec::new(),
+            None => vec![FailedExpectation::MissingGoldenFile {
+                stream: Stream::StdOut,
+                golden: out_file.to_string(),
+                actual: stdout.to_string(),
+            }],
+        });
+    }
+
     match &test.out {
-        Some(expected_out) if stdout.ne(expected_out) => {
-            Some(FailedExpectation::StdOut(Expectation {
+        Some(OutputExpectation::Exact(expected_out)) if stdout.ne(expected_out) => {
+            Ok(vec![FailedExpectation::StdOut(Expectation {
                 actual: stdout.to_string(),
                 expected: expected_out.to_string(),
-            }))
+            })])
         }
-        _ => None,
+        Some(OutputExpectation::Matching(matchers)) => {
+            verify_matchers(Stream::StdOut, matchers, stdout)
+        }
+        _ => Ok(Vec::new()),
     }
 }
 
-fn verify_stderr(test: &super::Test, stderr: &str) -> Option<FailedExpectation> {
+fn verify_stderr(
+    test: &super::Test,
+    stderr: &str,
+    base_dir: &Path,
+) -> Result<Vec<FailedExpectation>, errors::CliError> {
+    if let Some(err_file) = &test.err_file {
+        return Ok(match read_golden(test, err_file, base_dir)? {
+            Some(expected_err) if stderr.ne(&expected_err) => vec![FailedExpectation::StdErrFile(
+                err_file.to_string(),
+                Expectation {
+                    actual: stderr.to_string(),
+                    expected: expected_err,
+                },
+            )],
+            Some(_) => Vec::new(),
+            None => vec![FailedExpectation::MissingGoldenFile {
+                stream: Stream::StdErr,
+                golden: err_file.to_string(),
+                actual: stderr.to_string(),
+            }],
+        });
+    }
+
     match &test.err {
-        Some(expected_err) if stderr.ne(expected_err) => {
-            Some(FailedExpectation::StdErr(Expectation {
+        Some(OutputExpectation::Exact(expected_err)) if stderr.ne(expected_err) => {
+            Ok(vec![FailedExpectation::StdErr(Expectation {
                 actual: stderr.to_string(),
                 expected: expected_err.to_string(),
-            }))
+            })])
         }
-        _ => None,
+        Some(OutputExpectation::Matching(matchers)) => {
+            verify_matchers(Stream::StdErr, matchers, stderr)
+        }
+        _ => Ok(Vec::new()),
     }
 }
 
+/// The contents of a golden file, or `None` if it doesn't exist yet.
+fn read_golden(
+    test: &super::Test,
+    golden: &str,
+    base_dir: &Path,
+) -> Result<Option<String>, errors::CliError> {
+    match fs::read_to_string(base_dir.join(golden)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(errors::CliError::TestFile(
+            test.name.clone(),
+            golden.to_string(),
+            err,
+        )),
+    }
+}
+
+fn verify_matchers(
+    stream: Stream,
+    matchers: &OutputMatchers,
+    actual: &str,
+) -> Result<Vec<FailedExpectation>, errors::CliError> {
+    let mut failed_expectations: Vec<FailedExpectation> = Vec::new();
+
+    if let Some(ref pattern) = matchers.matches {
+        if !Regex::new(pattern)?.is_match(actual) {
+            failed_expectations.push(FailedExpectation::PatternMismatch {
+                stream: stream.clone(),
+                pattern: pattern.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+
+    for fragment in &matchers.contains {
+        if !actual.contains(fragment.as_str()) {
+            failed_expectations.push(FailedExpectation::MissingFragment {
+                stream: stream.clone(),
+                fragment: fragment.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+
+    for fragment in &matchers.not_contains {
+        if actual.contains(fragment.as_str()) {
+            failed_expectations.push(FailedExpectation::UnexpectedFragment {
+                stream: stream.clone(),
+                fragment: fragment.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+
+    Ok(failed_expectations)
+}
+
+fn verify_files(
+    test: &super::Test,
+    dir: &Path,
+) -> Result<Vec<FailedExpectation>, errors::CliError> {
+    let mut failed_expectations: Vec<FailedExpectation> = Vec::new();
+
+    // Sorted so that failures are reported in a stable order.
+    let mut paths: Vec<&String> = test.expect_files.keys().collect();
+    paths.sort();
+
+    for path in paths {
+        let expectation = &test.expect_files[path];
+        let full_path = dir.join(path);
+        // A directory at the path doesn't count as the file existing.
+        let exists = full_path.is_file();
+
+        let expected_exists = match *expectation {
+            FileExpectation::Exact(_) => true,
+            FileExpectation::Matching(ref matchers) => matchers.exists,
+        };
+
+        if exists != expected_exists {
+            failed_expectations.push(if expected_exists {
+                FailedExpectation::MissingFile(path.clone())
+            } else {
+                FailedExpectation::UnexpectedFile(path.clone())
+            });
+            continue;
+        }
+
+        if !exists {
+            continue;
+        }
+
+        // Files aren't necessarily text, so invalid UTF-8 is replaced rather
+        // than stopping the run.
+        let contents = fs::read(&full_path)
+            .map_err(|err| errors::CliError::TestFile(test.name.clone(), path.clone(), err))?;
+        let actual = String::from_utf8_lossy(&contents).into_owned();
+
+        match *expectation {
+            FileExpectation::Exact(ref expected) if actual.ne(expected) => {
+                failed_expectations.push(FailedExpectation::File(
+                    path.clone(),
+                    Expectation {
+                        actual,
+                        expected: expected.to_string(),
+                    },
+                ));
+            }
+            FileExpectation::Matching(ref matchers) => {
+                failed_expectations.extend(verify_matchers(
+                    Stream::File(path.clone()),
+                    &matchers.contents,
+                    &actual,
+                )?);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(failed_expectations)
+}
+
+fn verify_tree(
+    tree: &TreeExpectation,
+    base_dir: &Path,
+    dir: &Path,
+) -> Result<Vec<FailedExpectation>, errors::CliError> {
+    let changes = tree::compare(
+        &dir.join(&tree.path),
+        &base_dir.join(&tree.golden),
+        tree.permissions,
+    )?;
+
+    Ok(changes
+        .into_iter()
+        .map(|(file, change)| FailedExpectation::Tree {
+            path: tree.path.clone(),
+            golden: tree.golden.clone(),
+            file,
+            change,
+        })
+        .collect())
+}
+
 fn verify_exit_code(test: &super::Test, exit_code: Option<i32>) -> Option<FailedExpectation> {
     match (test.exit_code, exit_code) {
         (Some(expected_exit_code), Some(exit_code)) if exit_code != expected_exit_code => {
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use regex::Regex;
+
+/// Selects tests by name, for `--filter` and `--exclude`.
+#[derive(Clone, Debug)]
+pub enum NamePattern {
+    /// Matches names that contain the text.
+    Substring(String),
+    /// Matches names that the regex matches anywhere within. Written as
+    /// `/regex/`.
+    Regex(Regex),
+}
+
+impl NamePattern {
+    pub fn matches(&self, name: &str) -> bool {
+        match *self {
+            NamePattern::Substring(ref text) => name.contains(text.as_str()),
+            NamePattern::Regex(ref regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<NamePattern, regex::Error> {
+        if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+            let regex = Regex::new(&pattern[1..pattern.len() - 1])?;
+
+            Ok(NamePattern::Regex(regex))
+        } else {
+            Ok(NamePattern::Substring(pattern.to_string()))
+        }
+    }
+}
+
+/// Whether a test should run given the `--filter` and `--exclude` patterns.
+/// With no filters, every test that isn't excluded runs.
+pub fn is_selected(name: &str, filters: &[NamePattern], excludes: &[NamePattern]) -> bool {
+    let included = filters.is_empty() || filters.iter().any(|filter| filter.matches(name));
+    let excluded = excludes.iter().any(|exclude| exclude.matches(name));
+
+    included && !excluded
+}
@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+
+use crate::errors::CliError;
+
+/// A file to create in a test's working directory before it runs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FileFixture {
+    /// The file's contents.
+    Contents(String),
+    /// A file to copy in.
+    Copy(CopyFixture),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopyFixture {
+    /// The path of the file to copy, relative to the test file.
+    pub copy: String,
+}
+
+/// Whether `path` stays inside the directory it's relative to, so that a
+/// fixture can't overwrite files elsewhere.
+pub fn is_valid_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Creates each of the test's `files` in `dir`, along with any missing
+/// parent directories. Existing files are overwritten.
+pub fn write(test: &super::Test, base_dir: &Path, dir: &Path) -> Result<(), CliError> {
+    for (path, fixture) in &test.files {
+        let in_test = |source: &str, err: io::Error| {
+            CliError::TestFile(test.name.clone(), source.to_string(), err)
+        };
+        let target = dir.join(path);
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| in_test(path, err))?;
+        }
+
+        match *fixture {
+            FileFixture::Contents(ref contents) => {
+                fs::write(&target, contents).map_err(|err| in_test(path, err))?
+            }
+            FileFixture::Copy(ref source) => {
+                fs::copy(base_dir.join(&source.copy), &target)
+                    .map_err(|err| in_test(&source.copy, err))?;
+            }
+        }
+    }
+
+    Ok(())
+}
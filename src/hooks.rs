@@ -0,0 +1,144 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use ansi_term::Colour;
+
+use crate::command;
+use crate::errors;
+use crate::expectations::{Detail, Failure};
+
+/// A shell snippet that runs around the tests in a file, or around a single
+/// test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hook {
+    BeforeAll,
+    AfterAll,
+    Before,
+    After,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hook::BeforeAll => write!(f, "before_all"),
+            Hook::AfterAll => write!(f, "after_all"),
+            Hook::Before => write!(f, "before"),
+            Hook::After => write!(f, "after"),
+        }
+    }
+}
+
+/// A hook that couldn't start, exited with a non-zero code, was killed by a
+/// signal, or timed out. This is an error in the setup of a test rather than
+/// a test failure.
+#[derive(Clone, Debug)]
+pub struct HookFailure {
+    hook: Hook,
+    exit_code: Option<i32>,
+    timed_out: Option<Duration>,
+    not_started: Option<String>,
+    stdout: String,
+    stderr: String,
+}
+
+impl fmt::Display for HookFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stdout = Colour::Red.paint(&self.stdout).to_string();
+        let stderr = Colour::Red.paint(&self.stderr).to_string();
+
+        write!(f, "{}", report(&self.headline(), &stdout, &stderr))
+    }
+}
+
+impl HookFailure {
+    pub fn hook(&self) -> Hook {
+        self.hook
+    }
+
+    fn headline(&self) -> String {
+        if let Some(reason) = &self.not_started {
+            return format!("The {} hook didn't start. {}.", self.hook, reason);
+        }
+
+        match (self.timed_out, self.exit_code) {
+            (Some(timeout), _) => format!("The {} hook timed out after {:?}.", self.hook, timeout),
+            (None, Some(code)) => format!("The {} hook exited with code {}.", self.hook, code),
+            (None, None) => format!("The {} hook was killed by a signal.", self.hook),
+        }
+    }
+}
+
+impl Failure for HookFailure {
+    fn kind(&self) -> &'static str {
+        "hook"
+    }
+
+    fn details(&self) -> Vec<(&'static str, Detail)> {
+        let mut details = vec![("hook", Detail::Text(self.hook.to_string()))];
+
+        if let Some(code) = self.exit_code {
+            details.push(("exit_code", Detail::Integer(code.into())));
+        }
+
+        if let Some(timeout) = self.timed_out {
+            details.push(("timeout", Detail::Seconds(timeout.as_secs_f64())));
+        }
+
+        if let Some(reason) = &self.not_started {
+            details.push(("reason", Detail::Text(reason.clone())));
+        }
+
+        details.push(("stdout", Detail::Text(self.stdout.clone())));
+        details.push(("stderr", Detail::Text(self.stderr.clone())));
+
+        details
+    }
+
+    fn plain_report(&self, _diff: bool) -> String {
+        report(&self.headline(), &self.stdout, &self.stderr)
+    }
+}
+
+fn report(summary: &str, stdout: &str, stderr: &str) -> String {
+    format!(
+        "    {}\n\
+        \n\
+        \x20   Received on stdout:\n\
+        \n\
+        \x20     {}\n\
+        \n\
+        \x20   Received on stderr:\n\
+        \n\
+        \x20     {}\n",
+        summary, stdout, stderr
+    )
+}
+
+/// Runs a hook with the same working directory and environment as `test`,
+/// or as the file's tests for file-level hooks.
+pub fn run(
+    hook: Hook,
+    script: &str,
+    test: Option<&super::Test>,
+    suite: &super::Suite,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<Option<HookFailure>, errors::CliError> {
+    let command = command::shell(script, test, suite, base_dir, tmpdir);
+    let output = command::execute(command, None, timeout)?;
+
+    if output.timed_out.is_none() && output.exit_code == Some(0) {
+        return Ok(None);
+    }
+
+    Ok(Some(HookFailure {
+        hook,
+        exit_code: output.exit_code,
+        timed_out: output.timed_out,
+        not_started: output.not_started,
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }))
+}
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::expectations::{Detail, Failure};
+use crate::hooks::HookFailure;
+use crate::TestStatus;
+
+/// The version of the event schema. It's bumped whenever a field is removed
+/// or changes meaning; new fields and events may be added without a bump.
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    RunStarted {
+        version: u32,
+        files: &'a [&'a str],
+        tests: usize,
+    },
+    SuiteStarted {
+        version: u32,
+        file: &'a str,
+        tests: usize,
+    },
+    TestStarted {
+        version: u32,
+        file: &'a str,
+        number: usize,
+        name: &'a str,
+    },
+    TestFinished {
+        version: u32,
+        file: &'a str,
+        number: usize,
+        name: &'a str,
+        status: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        skip_reason: Option<&'a str>,
+        duration: f64,
+        stdout: &'a str,
+        stderr: &'a str,
+        failures: Vec<Map<String, Value>>,
+        errors: Vec<Map<String, Value>>,
+    },
+    SuiteFinished {
+        version: u32,
+        file: &'a str,
+        duration: f64,
+        errors: Vec<Map<String, Value>>,
+        counts: &'a super::TestCounts,
+        total: usize,
+    },
+    RunFinished {
+        version: u32,
+        duration: f64,
+        counts: &'a super::TestCounts,
+        total: usize,
+    },
+}
+
+pub fn run_started(files: &[&str], tests: usize) -> String {
+    line(&Event::RunStarted {
+        version: SCHEMA_VERSION,
+        files,
+        tests,
+    })
+}
+
+pub fn suite_started(file: &str, tests: usize) -> String {
+    line(&Event::SuiteStarted {
+        version: SCHEMA_VERSION,
+        file,
+        tests,
+    })
+}
+
+pub fn test_started(file: &str, number: usize, test: &super::Test) -> String {
+    line(&Event::TestStarted {
+        version: SCHEMA_VERSION,
+        file,
+        number,
+        name: &test.name,
+    })
+}
+
+pub fn test_finished(
+    file: &str,
+    number: usize,
+    test: &super::Test,
+    result: &super::TestResult,
+) -> String {
+    let status = match result.status() {
+        TestStatus::Passed => "passed",
+        TestStatus::Failed => "failed",
+        TestStatus::Updated => "updated",
+        TestStatus::Skipped => "skipped",
+        TestStatus::NotRun => "not_run",
+        TestStatus::Errored => "errored",
+    };
+
+    let failures = result
+        .failed_expectations
+        .iter()
+        .map(|expectation| {
+            let mut failure = describe(expectation);
+            failure.insert(String::from("type"), Value::from(expectation.kind()));
+            failure
+        })
+        .collect();
+
+    line(&Event::TestFinished {
+        version: SCHEMA_VERSION,
+        file,
+        number,
+        name: &test.name,
+        status,
+        skip_reason: if result.skipped {
+            test.skip_reason()
+        } else {
+            None
+        },
+        duration: result.duration.as_secs_f64(),
+        stdout: &result.stdout,
+        stderr: &result.stderr,
+        failures,
+        errors: errors(&result.hook_failures),
+    })
+}
+
+pub fn suite_finished(file: &str, summary: &super::FileSummary) -> String {
+    line(&Event::SuiteFinished {
+        version: SCHEMA_VERSION,
+        file,
+        duration: summary.duration.as_secs_f64(),
+        errors: errors(&summary.hook_failures),
+        counts: &summary.counts,
+        total: summary.counts.total(),
+    })
+}
+
+pub fn run_finished(duration: Duration, counts: &super::TestCounts) -> String {
+    line(&Event::RunFinished {
+        version: SCHEMA_VERSION,
+        duration: duration.as_secs_f64(),
+        counts,
+        total: counts.total(),
+    })
+}
+
+fn errors(hook_failures: &[HookFailure]) -> Vec<Map<String, Value>> {
+    hook_failures
+        .iter()
+        .map(|hook_failure| describe(hook_failure))
+        .collect()
+}
+
+fn describe(failure: &dyn Failure) -> Map<String, Value> {
+    let mut description = Map::new();
+
+    description.insert(String::from("message"), Value::from(failure.summary()));
+
+    for (name, detail) in failure.details() {
+        let value = match detail {
+            Detail::Text(text) => Value::from(text),
+            Detail::Integer(integer) => Value::from(integer),
+            Detail::Seconds(seconds) => Value::from(seconds),
+        };
+
+        description.insert(String::from(name), value);
+    }
+
+    description
+}
+
+fn line(event: &Event) -> String {
+    // Events only hold strings, numbers, and maps with string keys, none of
+    // which can fail to serialize.
+    let mut line = serde_json::to_string(event).expect("events are always serializable");
+    line.push('\n');
+    line
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use crate::expectations::Failure;
+use crate::TestStatus;
+
+/// Renders a JUnit XML report from the `<testsuite>` elements of each test
+/// file.
+pub fn render(
+    suites: &[String],
+    files: &[super::FileSummary],
+    counts: &super::TestCounts,
+    duration: Duration,
+) -> String {
+    let after_all_failures: usize = files.iter().map(after_all_cases).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    // Updated tests count as passed, and filtered tests aren't included.
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{}\">\n",
+        counts.total() - counts.filtered + after_all_failures,
+        counts.failed,
+        counts.errored + after_all_failures,
+        seconds(duration)
+    ));
+
+    for suite in suites {
+        xml.push_str(suite);
+    }
+
+    xml.push_str("</testsuites>\n");
+
+    xml
+}
+
+/// Renders a `<testsuite>` for a test file, with a `<testcase>` for each
+/// test. A failed `after_all` hook gets a `<testcase>` of its own.
+pub fn render_suite(
+    filename: &str,
+    tests: &[super::Test],
+    results: &[super::TestResult],
+    summary: &super::FileSummary,
+    diff: bool,
+) -> String {
+    let count = |status: TestStatus| {
+        results
+            .iter()
+            .filter(|result| result.status() == status)
+            .count()
+    };
+    let after_all_failures = after_all_cases(summary);
+
+    let mut xml = format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+        escape(filename),
+        results.len() + after_all_failures,
+        count(TestStatus::Failed),
+        count(TestStatus::Errored) + after_all_failures,
+        count(TestStatus::Skipped) + count(TestStatus::NotRun),
+        seconds(summary.duration)
+    );
+
+    for (test, result) in tests.iter().zip(results) {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\">\n",
+            escape(&test.name),
+            escape(filename),
+            seconds(result.duration)
+        ));
+
+        if result.not_run {
+            xml.push_str("      <skipped message=\"not run\"/>\n");
+        } else if result.skipped {
+            match test.skip_reason {
+                Some(ref reason) => xml.push_str(&format!(
+                    "      <skipped message=\"{}\"/>\n",
+                    escape(reason)
+                )),
+                None => xml.push_str("      <skipped/>\n"),
+            }
+        }
+
+        for hook_failure in &result.hook_failures {
+            xml.push_str(&element("error", hook_failure, diff));
+        }
+
+        if !result.updated {
+            for expectation in &result.failed_expectations {
+                xml.push_str(&element("failure", expectation, diff));
+            }
+        }
+
+        xml.push_str(&format!(
+            "      <system-out>{}</system-out>\n",
+            escape(&result.stdout)
+        ));
+        xml.push_str(&format!(
+            "      <system-err>{}</system-err>\n",
+            escape(&result.stderr)
+        ));
+        xml.push_str("    </testcase>\n");
+    }
+
+    for hook_failure in &summary.hook_failures {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"0.000\">\n",
+            hook_failure.hook(),
+            escape(filename)
+        ));
+        xml.push_str(&element("error", hook_failure, diff));
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n");
+
+    xml
+}
+
+fn after_all_cases(summary: &super::FileSummary) -> usize {
+    summary.hook_failures.len()
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+fn element(name: &str, failure: &dyn Failure, diff: bool) -> String {
+    format!(
+        "      <{} message=\"{}\">{}</{}>\n",
+        name,
+        escape(&failure.summary()),
+        escape(&failure.plain_report(diff)),
+        name
+    )
+}
+
+/// Escapes text for use in XML content and attribute values. Characters that
+/// XML doesn't allow at all (most control characters) are replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() && c < '\u{80}' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -1,70 +1,362 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs;
-use std::process::Command;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ansi_term::{Colour, Style};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use tmpdir::TempDir;
+
+mod command;
+mod diff;
+mod discover;
 mod errors;
 mod expectations;
+mod filter;
+mod fixtures;
+mod hooks;
+mod json;
+mod junit;
+mod parallel;
+mod reporter;
+mod tags;
+mod tap;
+mod tmpdir;
+mod tree;
+mod update;
+mod yaml;
+
+pub use errors::{CliError, ValidationError};
+pub use expectations::{Detail, FailedExpectation, Failure};
+pub use filter::NamePattern;
+pub use hooks::{Hook, HookFailure};
+pub use reporter::{DotReporter, JUnitReporter, JsonReporter, Notice, Reporter, TapReporter};
+pub use tags::{TagExpression, TagExpressionError};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Test {
     #[serde(rename = "test")]
     name: String,
     #[serde(rename = "in")]
-    input: String,
-    out: Option<String>,
-    err: Option<String>,
+    input: command::Input,
+    stdin: Option<String>,
+    stdin_file: Option<String>,
+    out: Option<expectations::OutputExpectation>,
+    err: Option<expectations::OutputExpectation>,
+    out_file: Option<String>,
+    err_file: Option<String>,
     exit_code: Option<i32>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    env_clear: Option<bool>,
+    cwd: Option<String>,
+    timeout: Option<f64>,
+    #[serde(default)]
+    serial: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    skip: bool,
+    skip_reason: Option<String>,
+    #[serde(default)]
+    only: bool,
+    tmpdir: Option<bool>,
+    #[serde(default)]
+    files: HashMap<String, fixtures::FileFixture>,
+    #[serde(default)]
+    expect_files: HashMap<String, expectations::FileExpectation>,
+    expect_tree: Option<expectations::TreeExpectation>,
+    before: Option<String>,
+    after: Option<String>,
 }
 
-struct Failure {
-    name: String,
-    failure_number: usize,
-    failed_expectations: Vec<expectations::FailedExpectation>,
+impl Test {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Why the test is skipped, if it's marked `skip` and gives a reason.
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.skip_reason.as_deref()
+    }
 }
 
-impl fmt::Display for Failure {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "  {}) {}\n\n",
-            self.failure_number,
-            Colour::Red.paint(&self.name)
-        )?;
+/// A test file. Files are either a plain list of tests or a mapping that
+/// sets file-level defaults alongside a `tests` list.
+#[derive(Debug, Default, Deserialize)]
+pub struct Suite {
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    env_clear: bool,
+    before_all: Option<String>,
+    after_all: Option<String>,
+    tests: Vec<Test>,
+    /// How many tests were left out by `--filter`, `--exclude`, `--tags`,
+    /// `--skip-tags` and `only`.
+    #[serde(skip)]
+    filtered: usize,
+}
+
+/// The outcome of running a single test.
+#[derive(Default)]
+pub struct TestResult {
+    failed_expectations: Vec<expectations::FailedExpectation>,
+    stdout: String,
+    stderr: String,
+    duration: Duration,
+    updated: bool,
+    skipped: bool,
+    not_run: bool,
+    hook_failures: Vec<HookFailure>,
+}
 
-        for expectation in &self.failed_expectations {
-            expectation.fmt(f)?;
+impl TestResult {
+    pub fn status(&self) -> TestStatus {
+        if self.not_run {
+            TestStatus::NotRun
+        } else if self.skipped {
+            TestStatus::Skipped
+        } else if !self.hook_failures.is_empty() {
+            TestStatus::Errored
+        } else if self.failed_expectations.is_empty() {
+            TestStatus::Passed
+        } else if self.updated {
+            TestStatus::Updated
+        } else {
+            TestStatus::Failed
         }
+    }
 
-        Ok(())
+    /// Every expectation the test didn't meet. Empty if it passed.
+    pub fn failed_expectations(&self) -> &[FailedExpectation] {
+        &self.failed_expectations
     }
+
+    /// The hooks that failed around the test, including a failed
+    /// `before_all` hook of its file.
+    pub fn hook_failures(&self) -> &[HookFailure] {
+        &self.hook_failures
+    }
+
+    /// What the command wrote to stdout. Invalid UTF-8 is replaced.
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    /// What the command wrote to stderr. Invalid UTF-8 is replaced.
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    /// How long the command ran.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// How a test turned out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    /// The test failed, and its expectations were rewritten to match what
+    /// it received.
+    Updated,
+    /// The test is marked `skip`, so it didn't run.
+    Skipped,
+    /// The test didn't run because the run stopped after too many failures.
+    NotRun,
+    /// A hook failed, so the test couldn't be set up or cleaned up properly.
+    Errored,
 }
 
-#[derive(Debug)]
-struct TestCounts {
+#[derive(Debug, Default, Serialize)]
+pub struct TestCounts {
     passed: usize,
     failed: usize,
+    updated: usize,
+    skipped: usize,
+    not_run: usize,
+    errored: usize,
+    filtered: usize,
 }
 
 impl fmt::Display for TestCounts {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let label_text = Style::new().bold().paint("Tests:");
-        let passed_text = Colour::Green.paint(format!("{} passed", self.passed));
-        let failed_text = Colour::Red.paint(format!("{} failed", self.failed));
-        let total_text = format!("{} total", self.passed + self.failed);
-
-        match self.failed {
-            0 => writeln!(f, "{} {}, {}", label_text, passed_text, total_text),
-            _ => {
-                writeln!(
-                    f,
-                    "{} {}, {}, {}",
-                    label_text, passed_text, failed_text, total_text
-                )
-            }
+
+        writeln!(f, "{} {}", label_text, self.breakdown())
+    }
+}
+
+impl TestCounts {
+    /// The counts as a comma-separated list, leaving out outcomes that
+    /// didn't happen (other than passing).
+    fn breakdown(&self) -> String {
+        let mut counts = vec![Colour::Green
+            .paint(format!("{} passed", self.passed))
+            .to_string()];
+
+        if self.updated > 0 {
+            counts.push(
+                Colour::Yellow
+                    .paint(format!("{} updated", self.updated))
+                    .to_string(),
+            );
+        }
+
+        if self.skipped > 0 {
+            counts.push(
+                Colour::Cyan
+                    .paint(format!("{} skipped", self.skipped))
+                    .to_string(),
+            );
+        }
+
+        if self.failed > 0 {
+            counts.push(
+                Colour::Red
+                    .paint(format!("{} failed", self.failed))
+                    .to_string(),
+            );
+        }
+
+        if self.errored > 0 {
+            counts.push(
+                Colour::Red
+                    .paint(format!("{} errored", self.errored))
+                    .to_string(),
+            );
+        }
+
+        if self.not_run > 0 {
+            counts.push(format!("{} not run", self.not_run));
+        }
+
+        if self.filtered > 0 {
+            counts.push(format!("{} filtered", self.filtered));
+        }
+
+        counts.push(format!("{} total", self.total()));
+
+        counts.join(", ")
+    }
+
+    fn add(&mut self, other: &TestCounts) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.updated += other.updated;
+        self.skipped += other.skipped;
+        self.not_run += other.not_run;
+        self.errored += other.errored;
+        self.filtered += other.filtered;
+    }
+
+    pub fn passed(&self) -> usize {
+        self.passed
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    pub fn updated(&self) -> usize {
+        self.updated
+    }
+
+    /// Tests marked `skip`.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Tests with a failed hook.
+    pub fn errored(&self) -> usize {
+        self.errored
+    }
+
+    /// Tests that didn't run because the run stopped after too many
+    /// failures.
+    pub fn not_run(&self) -> usize {
+        self.not_run
+    }
+
+    /// Tests that didn't run because of `--filter`, `--exclude`, `--tags`,
+    /// `--skip-tags` or `only`.
+    pub fn filtered(&self) -> usize {
+        self.filtered
+    }
+
+    /// Every test in the test files, including those that didn't run.
+    pub fn total(&self) -> usize {
+        self.passed
+            + self.failed
+            + self.updated
+            + self.skipped
+            + self.not_run
+            + self.errored
+            + self.filtered
+    }
+
+    /// Tests that count towards `--max-failures`.
+    fn failures(&self) -> usize {
+        self.failed + self.errored
+    }
+}
+
+/// Settings for a run that aren't part of the test file itself.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// How long a test may run before it's killed. A test's own `timeout`
+    /// takes precedence.
+    pub timeout: Option<Duration>,
+    /// How many tests may run at the same time.
+    pub jobs: usize,
+    /// Whether to rewrite the expected output and exit code of failing tests
+    /// in the test file to match what was received.
+    pub update: bool,
+    /// Only tests whose names match one of these run. Every test runs if
+    /// there are none.
+    pub filters: Vec<NamePattern>,
+    /// Tests whose names match any of these don't run.
+    pub excludes: Vec<NamePattern>,
+    /// Only tests whose tags match this run.
+    pub tags: Option<TagExpression>,
+    /// Tests whose tags match this don't run.
+    pub skip_tags: Option<TagExpression>,
+    /// Stops running tests once this many have failed, across every file.
+    /// The rest are reported as not run.
+    pub max_failures: Option<usize>,
+    /// Whether the run is on a CI server. Tests marked `only` fail the run,
+    /// so that they can't be committed by accident.
+    pub ci: bool,
+    /// Whether every test gets a fresh temporary directory to run in, as if
+    /// it set `tmpdir: true`. A test's own `tmpdir` takes precedence.
+    pub isolate: bool,
+    /// Whether to leave temporary directories in place after their tests,
+    /// for debugging.
+    pub keep_tmp: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            timeout: None,
+            jobs: 1,
+            update: false,
+            filters: Vec::new(),
+            excludes: Vec::new(),
+            tags: None,
+            skip_tags: None,
+            max_failures: None,
+            ci: false,
+            isolate: false,
+            keep_tmp: false,
         }
     }
 }
@@ -74,35 +366,363 @@ pub enum TestState {
     Failed,
 }
 
-pub fn run(filename: &str) -> Result<TestState, errors::CliError> {
-    let tests = parse(filename)?;
+/// The outcome of running a single test file.
+pub struct FileSummary {
+    file: String,
+    counts: TestCounts,
+    duration: Duration,
+    not_updated: usize,
+    hook_failures: Vec<HookFailure>,
+}
+
+impl FileSummary {
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn counts(&self) -> &TestCounts {
+        &self.counts
+    }
+
+    /// How long it took to run every test in the file.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// A failed `after_all` hook, which doesn't belong to any one test.
+    pub fn hook_failures(&self) -> &[HookFailure] {
+        &self.hook_failures
+    }
+}
+
+/// Runs the tests in each of `paths`, passing results to `reporter` as they
+/// come in. Paths can be test files, directories to search for test files,
+/// or glob patterns.
+///
+/// Every file is parsed and validated before any tests run.
+pub fn run(
+    paths: &[&str],
+    options: &Options,
+    reporter: &mut dyn Reporter,
+) -> Result<TestState, CliError> {
+    let mut suites: Vec<(String, Suite)> = Vec::new();
+
+    for path in discover::test_files(paths)? {
+        let filename = path.to_string_lossy().into_owned();
+        let mut suite = load(&filename).map_err(|err| in_file(&filename, err))?;
+
+        select_tests(&mut suite, options);
+
+        suites.push((filename, suite));
+    }
+
+    let only = restrict_to_only(&mut suites);
+
+    let filenames: Vec<&str> = suites
+        .iter()
+        .map(|(filename, _)| filename.as_str())
+        .collect();
+    let test_count = suites.iter().map(|(_, suite)| suite.tests.len()).sum();
+
+    reporter.run_started(&filenames, test_count)?;
+
+    let started_at = Instant::now();
+    let mut summaries: Vec<FileSummary> = Vec::with_capacity(suites.len());
+    let mut test_counts = TestCounts::default();
+
+    for (filename, suite) in suites {
+        let summary = run_file(&filename, suite, options, test_counts.failures(), reporter)
+            .map_err(|err| in_file(&filename, err))?;
+
+        test_counts.add(&summary.counts);
+        summaries.push(summary);
+    }
+
+    match tmpdir::root() {
+        Some(root) if options.keep_tmp => reporter.notice(&Notice::KeptTmp(root))?,
+        _ => tmpdir::remove_root(),
+    }
+
+    if options.ci {
+        for (file, test) in &only {
+            reporter.notice(&Notice::OnlyOnCi {
+                file: file.clone(),
+                test: test.clone(),
+            })?;
+        }
+    }
+
+    reporter.run_finished(&summaries, &test_counts, started_at.elapsed())?;
+
+    let passed = summaries.iter().all(|summary| {
+        summary.counts.failures() == 0
+            && summary.not_updated == 0
+            && summary.hook_failures.is_empty()
+    }) && (only.is_empty() || !options.ci);
+
+    if passed {
+        Ok(TestState::Passed)
+    } else {
+        Ok(TestState::Failed)
+    }
+}
+
+fn in_file(filename: &str, err: errors::CliError) -> errors::CliError {
+    errors::CliError::InFile(filename.to_string(), Box::new(err))
+}
+
+fn load(filename: &str) -> Result<Suite, errors::CliError> {
+    let suite = parse(filename)?;
+
+    validate_tests(&suite.tests)?;
+
+    Ok(suite)
+}
+
+/// Runs the tests in a file. `failed_before` is how many tests failed in
+/// earlier files, for `--max-failures`.
+fn run_file(
+    filename: &str,
+    suite: Suite,
+    options: &Options,
+    failed_before: usize,
+    reporter: &mut dyn Reporter,
+) -> Result<FileSummary, errors::CliError> {
+    let suite = Arc::new(suite);
+    let base_dir = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .to_path_buf();
 
     let mut test_counts = TestCounts {
-        passed: 0,
-        failed: 0,
+        filtered: suite.filtered,
+        ..TestCounts::default()
+    };
+    let mut results: Vec<TestResult> = Vec::with_capacity(suite.tests.len());
+    let mut updates: Vec<update::Update> = Vec::new();
+    let mut not_updated: Vec<String> = Vec::new();
+    let started_at = Instant::now();
+
+    // Read up front so that tests whose expectations can't be located are
+    // reported as failed rather than updated.
+    let contents = if options.update {
+        fs::read_to_string(filename)?
+    } else {
+        String::new()
     };
 
-    let mut failures: Vec<Failure> = Vec::new();
+    // Failures so far across every file, which tests check before they
+    // start so that none run once the limit is reached.
+    let failed = Arc::new(AtomicUsize::new(failed_before));
+
+    reporter.suite_started(filename, &suite.tests)?;
+
+    // File-level hooks only run if some of the tests will.
+    let run_file_hooks =
+        !failure_limit_reached(options, failed_before) && suite.tests.iter().any(|test| !test.skip);
+
+    let before_all_failure = match &suite.before_all {
+        Some(script) if run_file_hooks => hooks::run(
+            hooks::Hook::BeforeAll,
+            script,
+            None,
+            &suite,
+            &base_dir,
+            None,
+            options.timeout,
+        )?,
+        _ => None,
+    };
 
-    validate_tests(&tests)?;
+    for batch in batches(&suite.tests) {
+        let start = batch.start;
+
+        // Nothing in the batch gets scheduled once too many tests have
+        // failed, or if the file couldn't be set up.
+        let count = if failure_limit_reached(options, failed_before + test_counts.failures())
+            || before_all_failure.is_some()
+        {
+            0
+        } else {
+            batch.len()
+        };
+
+        let mut batch_results = {
+            let suite = Arc::clone(&suite);
+            let base_dir = base_dir.clone();
+            let options = options.clone();
+            let failed = Arc::clone(&failed);
+
+            parallel::map_ordered(count, options.jobs, move |index| {
+                if failure_limit_reached(&options, failed.load(Ordering::SeqCst)) {
+                    return Ok(TestResult {
+                        not_run: true,
+                        ..TestResult::default()
+                    });
+                }
+
+                run_test(&suite.tests[start + index], &suite, &base_dir, &options)
+            })
+        };
+
+        // Results come back in test order, so progress and failure numbering
+        // don't depend on which test happens to finish first.
+        for index in batch {
+            let test = &suite.tests[index];
+
+            let mut result = if test.skip && before_all_failure.is_some() {
+                // Skipped tests don't depend on `before_all`, so they're
+                // reported as skipped as usual.
+                reporter.test_started(filename, index + 1, test)?;
+
+                TestResult {
+                    skipped: true,
+                    ..TestResult::default()
+                }
+            } else if let Some(failure) = &before_all_failure {
+                TestResult {
+                    hook_failures: vec![failure.clone()],
+                    ..TestResult::default()
+                }
+            } else if count == 0 {
+                TestResult {
+                    not_run: true,
+                    ..TestResult::default()
+                }
+            } else if failure_limit_reached(options, failed.load(Ordering::SeqCst)) {
+                // Tests that started before the limit was reached still
+                // report their results. The rest don't run at all.
+                let result = batch_results
+                    .next()
+                    .expect("every scheduled test has a result")?;
+
+                if !result.not_run {
+                    reporter.test_started(filename, index + 1, test)?;
+                }
+
+                result
+            } else {
+                reporter.test_started(filename, index + 1, test)?;
+
+                batch_results
+                    .next()
+                    .expect("every scheduled test has a result")?
+            };
+
+            record_result(
+                test,
+                &mut result,
+                options,
+                &contents,
+                &mut test_counts,
+                &mut updates,
+                &mut not_updated,
+            );
+            failed.store(failed_before + test_counts.failures(), Ordering::SeqCst);
+            reporter.test_finished(filename, index + 1, test, &result)?;
+
+            results.push(result);
+        }
+    }
 
-    for test in tests {
-        run_test(test, &mut test_counts, &mut failures)?;
+    // `after_all` runs even if `before_all` failed, so that it can clean up
+    // whatever was set up.
+    let after_all_failure = match &suite.after_all {
+        Some(script) if run_file_hooks => hooks::run(
+            hooks::Hook::AfterAll,
+            script,
+            None,
+            &suite,
+            &base_dir,
+            None,
+            options.timeout,
+        )?,
+        _ => None,
+    };
+
+    let duration = started_at.elapsed();
+    if !updates.is_empty() {
+        not_updated.extend(update::rewrite(filename, &updates)?);
     }
 
-    report_summary(&test_counts, &failures);
+    let summary = FileSummary {
+        file: filename.to_string(),
+        counts: test_counts,
+        duration,
+        not_updated: not_updated.len(),
+        hook_failures: after_all_failure.into_iter().collect(),
+    };
+
+    reporter.suite_finished(filename, &suite.tests, &results, &summary)?;
 
-    match test_counts.failed {
-        0 => Ok(TestState::Passed),
-        _ => Ok(TestState::Failed),
+    for test in not_updated {
+        reporter.notice(&Notice::NotUpdated {
+            file: filename.to_string(),
+            test,
+        })?;
     }
+
+    Ok(summary)
 }
 
-fn parse(filename: &str) -> Result<Vec<Test>, errors::CliError> {
+fn parse(filename: &str) -> Result<Suite, errors::CliError> {
     let contents = fs::read_to_string(filename)?;
-    let tests: Vec<Test> = serde_yaml::from_str(&contents)?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&contents)?;
+
+    if value.is_sequence() {
+        let tests: Vec<Test> = serde_yaml::from_value(value)?;
+
+        return Ok(Suite {
+            tests,
+            ..Suite::default()
+        });
+    }
+
+    let suite: Suite = serde_yaml::from_value(value)?;
+
+    Ok(suite)
+}
 
-    Ok(tests)
+/// Leaves out the tests that the name and tag filters don't select. This
+/// happens after validation so that every test in the file is checked.
+fn select_tests(suite: &mut Suite, options: &Options) {
+    let count = suite.tests.len();
+
+    suite.tests.retain(|test| {
+        filter::is_selected(&test.name, &options.filters, &options.excludes)
+            && tags::is_selected(
+                &test.tags,
+                options.tags.as_ref(),
+                options.skip_tags.as_ref(),
+            )
+    });
+    suite.filtered = count - suite.tests.len();
+}
+
+/// If any test is marked `only`, leaves out every test that isn't. Returns
+/// the files and names of the tests marked `only`.
+fn restrict_to_only(suites: &mut [(String, Suite)]) -> Vec<(String, String)> {
+    let only: Vec<(String, String)> = suites
+        .iter()
+        .flat_map(|(filename, suite)| {
+            suite
+                .tests
+                .iter()
+                .filter(|test| test.only)
+                .map(move |test| (filename.clone(), test.name.clone()))
+        })
+        .collect();
+
+    if !only.is_empty() {
+        for (_, suite) in suites.iter_mut() {
+            let count = suite.tests.len();
+
+            suite.tests.retain(|test| test.only);
+            suite.filtered += count - suite.tests.len();
+        }
+    }
+
+    only
 }
 
 fn validate_tests(tests: &[Test]) -> Result<(), errors::CliError> {
@@ -115,53 +735,285 @@ fn validate_tests(tests: &[Test]) -> Result<(), errors::CliError> {
                 errors::ValidationError::DuplicateTestName(test.name.clone()),
             ));
         }
+
+        if matches!(test.input, command::Input::Argv(ref argv) if argv.is_empty()) {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::EmptyCommand(test.name.clone()),
+            ));
+        }
+
+        if test.stdin.is_some() && test.stdin_file.is_some() {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::ConflictingStdin(test.name.clone()),
+            ));
+        }
+
+        if let Some(path) = test
+            .files
+            .keys()
+            .chain(test.expect_files.keys())
+            .chain(test.expect_tree.iter().map(|tree| &tree.path))
+            .find(|path| !fixtures::is_valid_path(path))
+        {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::InvalidFilePath(test.name.clone(), path.clone()),
+            ));
+        }
+
+        if test.out.is_some() && test.out_file.is_some() {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::ConflictingOutput(test.name.clone(), "out"),
+            ));
+        }
+
+        if test.err.is_some() && test.err_file.is_some() {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::ConflictingOutput(test.name.clone(), "err"),
+            ));
+        }
+
+        if test.cwd.is_some() && test.tmpdir == Some(true) {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::ConflictingCwd(test.name.clone()),
+            ));
+        }
+
+        match test.timeout {
+            Some(seconds) if !is_valid_timeout(seconds) => {
+                return Err(errors::CliError::Validation(
+                    errors::ValidationError::InvalidTimeout(test.name.clone()),
+                ));
+            }
+            _ => (),
+        }
+
+        if let Some(tag) = test.tags.iter().find(|tag| !tags::is_valid_tag(tag)) {
+            return Err(errors::CliError::Validation(
+                errors::ValidationError::InvalidTag(test.name.clone(), tag.clone()),
+            ));
+        }
+
+        let patterns = test
+            .out
+            .iter()
+            .chain(&test.err)
+            .flat_map(|expectation| expectation.patterns())
+            .chain(
+                test.expect_files
+                    .values()
+                    .flat_map(|expectation| expectation.patterns()),
+            );
+        for pattern in patterns {
+            if let Err(err) = regex::Regex::new(pattern) {
+                return Err(errors::CliError::Validation(
+                    errors::ValidationError::InvalidPattern(test.name.clone(), err),
+                ));
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Whether `seconds` can be turned into a `Duration` without panicking.
+pub fn is_valid_timeout(seconds: f64) -> bool {
+    seconds.is_finite() && seconds >= 0.0 && seconds < u64::MAX as f64
+}
+
+/// Splits tests into batches that can run concurrently. Every `serial` test
+/// gets a batch of its own.
+fn batches(tests: &[Test]) -> Vec<Range<usize>> {
+    let mut batches: Vec<Range<usize>> = Vec::new();
+    let mut start = 0;
+
+    for (index, test) in tests.iter().enumerate() {
+        if test.serial {
+            if start < index {
+                batches.push(start..index);
+            }
+
+            batches.push(index..index + 1);
+            start = index + 1;
+        }
+    }
+
+    if start < tests.len() {
+        batches.push(start..tests.len());
+    }
+
+    batches
+}
+
+fn failure_limit_reached(options: &Options, failed: usize) -> bool {
+    options.max_failures.map_or(false, |max| failed >= max)
+}
+
 fn run_test(
-    test: Test,
-    test_counts: &mut TestCounts,
-    failures: &mut Vec<Failure>,
-) -> Result<(), errors::CliError> {
-    let output = Command::new("bash").arg("-c").arg(&test.input).output()?;
+    test: &Test,
+    suite: &Suite,
+    base_dir: &Path,
+    options: &Options,
+) -> Result<TestResult, errors::CliError> {
+    if test.skip {
+        return Ok(TestResult {
+            skipped: true,
+            ..TestResult::default()
+        });
+    }
 
-    let failed_expectations = expectations::verify_expectations(&test, output)?;
+    let timeout = test
+        .timeout
+        .map(Duration::from_secs_f64)
+        .or(options.timeout);
 
-    if failed_expectations.is_empty() {
-        report_test_passed();
-        test_counts.passed += 1;
+    // The directory is removed when this returns, once the `after` hook has
+    // run, unless it's kept.
+    let tmpdir = if test.tmpdir.unwrap_or(options.isolate) {
+        Some(TempDir::create(&test.name, options.keep_tmp)?)
     } else {
-        report_test_failed();
-        test_counts.failed += 1;
+        None
+    };
 
-        failures.push(Failure {
-            name: test.name,
-            failure_number: test_counts.failed,
-            failed_expectations,
-        });
+    let fixtures = if test.files.is_empty() {
+        Ok(())
+    } else {
+        let dir = command::working_dir(Some(test), base_dir, tmpdir.as_ref().map(TempDir::path));
+
+        fixtures::write(test, base_dir, &dir.unwrap_or_default())
+    };
+
+    let mut hook_failures: Vec<HookFailure> = Vec::new();
+
+    if let (Ok(()), Some(script)) = (&fixtures, &test.before) {
+        hook_failures.extend(hooks::run(
+            hooks::Hook::Before,
+            script,
+            Some(test),
+            suite,
+            base_dir,
+            tmpdir.as_ref().map(TempDir::path),
+            timeout,
+        )?);
     }
 
-    Ok(())
-}
+    let result = match fixtures {
+        Err(err) => Err(err),
+        Ok(()) if hook_failures.is_empty() => execute_test(
+            test,
+            suite,
+            base_dir,
+            tmpdir.as_ref().map(TempDir::path),
+            timeout,
+        ),
+        Ok(()) => Ok(TestResult::default()),
+    }
+    .or_else(file_not_usable);
+
+    // `after` runs even if the test failed, or couldn't run at all.
+    if let Some(script) = &test.after {
+        hook_failures.extend(hooks::run(
+            hooks::Hook::After,
+            script,
+            Some(test),
+            suite,
+            base_dir,
+            tmpdir.as_ref().map(TempDir::path),
+            timeout,
+        )?);
+    }
 
-fn report_test_passed() {
-    print!("{}", Colour::Green.paint("."));
+    Ok(TestResult {
+        hook_failures,
+        ..result?
+    })
 }
 
-fn report_test_failed() {
-    print!("{}", Colour::Red.paint("F"));
+/// Turns a file the test needs but can't use, such as a missing
+/// `stdin_file`, into a failure of that test rather than of the whole run.
+fn file_not_usable(err: errors::CliError) -> Result<TestResult, errors::CliError> {
+    match err {
+        errors::CliError::TestFile(_, path, err) => Ok(TestResult {
+            failed_expectations: vec![FailedExpectation::NotStarted(format!(
+                "Couldn't use \"{}\": {}",
+                path, err
+            ))],
+            ..TestResult::default()
+        }),
+        err => Err(err),
+    }
 }
 
-fn report_summary(test_counts: &TestCounts, failures: &[Failure]) {
-    print!("\n\n{}", test_counts);
+fn execute_test(
+    test: &Test,
+    suite: &Suite,
+    base_dir: &Path,
+    tmpdir: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<TestResult, errors::CliError> {
+    let stdin = match (&test.stdin, &test.stdin_file) {
+        (Some(stdin), _) => Some(stdin.clone().into_bytes()),
+        (None, Some(stdin_file)) => {
+            let stdin = fs::read(base_dir.join(stdin_file)).map_err(|err| {
+                errors::CliError::TestFile(test.name.clone(), stdin_file.clone(), err)
+            })?;
+
+            Some(stdin)
+        }
+        (None, None) => None,
+    };
 
-    if !failures.is_empty() {
-        print!("\n{}\n\n", Style::new().bold().paint("Failures:"));
+    let started_at = Instant::now();
+    let output = command::execute(
+        command::build(test, suite, base_dir, tmpdir),
+        stdin,
+        timeout,
+    )?;
+    let duration = started_at.elapsed();
+
+    let dir = command::working_dir(Some(test), base_dir, tmpdir).unwrap_or_default();
+
+    Ok(TestResult {
+        failed_expectations: expectations::verify_expectations(test, &output, base_dir, &dir)?,
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        duration,
+        ..TestResult::default()
+    })
+}
 
-        for failure in failures.iter() {
-            print!("{}", failure);
+fn record_result(
+    test: &Test,
+    result: &mut TestResult,
+    options: &Options,
+    contents: &str,
+    test_counts: &mut TestCounts,
+    updates: &mut Vec<update::Update>,
+    not_updated: &mut Vec<String>,
+) {
+    match result.status() {
+        TestStatus::NotRun => test_counts.not_run += 1,
+        TestStatus::Skipped => test_counts.skipped += 1,
+        TestStatus::Errored => test_counts.errored += 1,
+        TestStatus::Passed => test_counts.passed += 1,
+        TestStatus::Failed | TestStatus::Updated => {
+            let update = if options.update {
+                expectations::update_for(test, &result.failed_expectations)
+            } else {
+                None
+            };
+
+            match update {
+                Some(update) if update::can_apply(contents, &update) => {
+                    test_counts.updated += 1;
+                    result.updated = true;
+                    updates.push(update);
+                }
+                Some(_) => {
+                    test_counts.failed += 1;
+                    not_updated.push(test.name.clone());
+                }
+                None => test_counts.failed += 1,
+            }
         }
     }
 }
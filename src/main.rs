@@ -1,20 +1,191 @@
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
 use std::process;
+use std::time::Duration;
 
 fn main() {
     let matches = App::new("CLI Test")
         .version("0.1.0")
         .about("A tiny test framework for CLIs")
         .arg(
-            Arg::with_name("file")
+            Arg::with_name("paths")
                 .required(true)
-                .help("The test file to run"),
+                .multiple(true)
+                .value_name("PATH")
+                .help("Test files, directories to search for *.yml and *.yaml test files, or glob patterns"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(validate_timeout)
+                .help("Kills tests that run longer than this (unless they set their own timeout)"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .takes_value(true)
+                .value_name("N")
+                .validator(validate_positive)
+                .help("Runs up to N tests at the same time (defaults to 1)"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN")
+                .validator(validate_pattern)
+                .help("Only runs tests whose names contain PATTERN, or match it if it's written as /regex/ (can be repeated)"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN")
+                .validator(validate_pattern)
+                .help("Doesn't run tests whose names contain PATTERN, or match it if it's written as /regex/ (can be repeated)"),
+        )
+        .arg(
+            Arg::with_name("tags")
+                .long("tags")
+                .takes_value(true)
+                .value_name("EXPRESSION")
+                .validator(validate_tag_expression)
+                .help("Only runs tests whose tags match EXPRESSION, such as \"smoke and not slow\""),
+        )
+        .arg(
+            Arg::with_name("skip-tags")
+                .long("skip-tags")
+                .takes_value(true)
+                .value_name("EXPRESSION")
+                .validator(validate_tag_expression)
+                .help("Doesn't run tests whose tags match EXPRESSION"),
+        )
+        .arg(
+            Arg::with_name("no-diff")
+                .long("no-diff")
+                .help("Prints expected and received output in full instead of as a diff"),
+        )
+        .arg(
+            Arg::with_name("update")
+                .long("update")
+                .visible_alias("bless")
+                .help("Rewrites the expected output and exit code of failing tests to match what they received"),
+        )
+        .arg(
+            Arg::with_name("reporter")
+                .long("reporter")
+                .takes_value(true)
+                .possible_values(&["dots", "junit", "tap", "json"])
+                .default_value("dots")
+                .help("How to report results"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the report to FILE instead of stdout (junit, tap and json reporters only)"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .conflicts_with("max-failures")
+                .help("Stops running tests after the first failure"),
+        )
+        .arg(
+            Arg::with_name("max-failures")
+                .long("max-failures")
+                .takes_value(true)
+                .value_name("N")
+                .validator(validate_positive)
+                .help("Stops running tests after N failures"),
+        )
+        .arg(
+            Arg::with_name("isolate")
+                .long("isolate")
+                .help("Runs every test in a fresh temporary directory, as if it set `tmpdir: true`"),
+        )
+        .arg(
+            Arg::with_name("keep-tmp")
+                .long("keep-tmp")
+                .help("Leaves tests' temporary directories in place instead of removing them"),
+        )
+        .arg(
+            Arg::with_name("ci")
+                .long("ci")
+                .help("Fails the run if any test is marked `only` (the default when the CI environment variable is set)"),
         )
         .get_matches();
 
-    let filename = matches.value_of("file").unwrap();
+    let paths: Vec<&str> = matches.values_of("paths").unwrap().collect();
+    let options = cli_test::Options {
+        timeout: matches
+            .value_of("timeout")
+            .map(|seconds| Duration::from_secs_f64(seconds.parse().unwrap())),
+        jobs: matches
+            .value_of("jobs")
+            .map_or(1, |jobs| jobs.parse().unwrap()),
+        update: matches.is_present("update"),
+        filters: name_patterns(&matches, "filter"),
+        excludes: name_patterns(&matches, "exclude"),
+        tags: matches
+            .value_of("tags")
+            .map(|expression| expression.parse().unwrap()),
+        skip_tags: matches
+            .value_of("skip-tags")
+            .map(|expression| expression.parse().unwrap()),
+        max_failures: if matches.is_present("fail-fast") {
+            Some(1)
+        } else {
+            matches
+                .value_of("max-failures")
+                .map(|max| max.parse().unwrap())
+        },
+        ci: matches.is_present("ci") || is_ci(),
+        isolate: matches.is_present("isolate"),
+        keep_tmp: matches.is_present("keep-tmp"),
+    };
 
-    match cli_test::run(filename) {
+    if matches.is_present("output") && matches.value_of("reporter") == Some("dots") {
+        clap::Error::with_description(
+            "--output can't be used with the dots reporter, which always prints to the terminal",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+
+    let output = || -> Box<dyn Write> {
+        match matches.value_of("output") {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            },
+            None => Box::new(io::stdout()),
+        }
+    };
+
+    let diff = !matches.is_present("no-diff");
+    let mut reporter: Box<dyn cli_test::Reporter> = match matches.value_of("reporter") {
+        Some("junit") => Box::new(cli_test::JUnitReporter::new(output(), diff)),
+        Some("tap") => Box::new(cli_test::TapReporter::new(output())),
+        Some("json") => Box::new(cli_test::JsonReporter::new(output())),
+        _ => Box::new(cli_test::DotReporter::new(diff)),
+    };
+
+    match cli_test::run(&paths, &options, reporter.as_mut()) {
         Ok(cli_test::TestState::Passed) => (),
         Ok(cli_test::TestState::Failed) => process::exit(1),
         Err(e) => {
@@ -23,3 +194,46 @@ fn main() {
         }
     }
 }
+
+/// Whether the `CI` environment variable, which most CI servers set, is set
+/// to something other than "false" or "0".
+fn is_ci() -> bool {
+    match env::var("CI") {
+        Ok(value) => !value.is_empty() && value != "false" && value != "0",
+        Err(_) => false,
+    }
+}
+
+fn validate_timeout(value: String) -> Result<(), String> {
+    match value.parse() {
+        Ok(seconds) if cli_test::is_valid_timeout(seconds) => Ok(()),
+        _ => Err(String::from("must be a non-negative number of seconds")),
+    }
+}
+
+fn validate_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(()),
+        _ => Err(String::from("must be a positive number")),
+    }
+}
+
+fn validate_pattern(value: String) -> Result<(), String> {
+    match value.parse::<cli_test::NamePattern>() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("invalid regex: {}", e)),
+    }
+}
+
+fn validate_tag_expression(value: String) -> Result<(), String> {
+    match value.parse::<cli_test::TagExpression>() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("invalid tag expression: {}", e)),
+    }
+}
+
+fn name_patterns(matches: &ArgMatches, name: &str) -> Vec<cli_test::NamePattern> {
+    matches.values_of(name).map_or_else(Vec::new, |values| {
+        values.map(|value| value.parse().unwrap()).collect()
+    })
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+type Job<T> = Arc<dyn Fn(usize) -> T + Send + Sync>;
+
+/// Results of `map_ordered`, yielded in index order regardless of the order
+/// in which the workers finish.
+pub struct OrderedResults<T> {
+    job: Job<T>,
+    jobs: usize,
+    next: usize,
+    count: usize,
+    workers: Option<Workers<T>>,
+}
+
+struct Workers<T> {
+    receiver: mpsc::Receiver<(usize, thread::Result<T>)>,
+    pending: HashMap<usize, T>,
+    schedule: Arc<(Mutex<Schedule>, Condvar)>,
+}
+
+/// Which indices the workers may pick up.
+struct Schedule {
+    next_index: usize,
+    /// Workers only start indices below this, so they never get more than
+    /// `jobs` ahead of the consumer.
+    limit: usize,
+    stopped: bool,
+}
+
+impl<T: Send + 'static> Iterator for OrderedResults<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.next == self.count {
+            return None;
+        }
+
+        let index = self.next;
+        self.next += 1;
+
+        if self.jobs == 1 {
+            return Some((self.job)(index));
+        }
+
+        let job = Arc::clone(&self.job);
+        let (jobs, count) = (self.jobs, self.count);
+        let workers = self
+            .workers
+            .get_or_insert_with(|| start_workers(job, jobs, count));
+
+        {
+            let (schedule, wake) = &*workers.schedule;
+            let mut schedule = schedule.lock().expect("a worker thread panicked");
+            schedule.limit = index + jobs;
+            wake.notify_all();
+        }
+
+        loop {
+            if let Some(result) = workers.pending.remove(&index) {
+                return Some(result);
+            }
+
+            match workers.receiver.recv() {
+                Ok((index, Ok(result))) => {
+                    workers.pending.insert(index, result);
+                }
+                // Pass the job's panic on to the consumer, as it would have
+                // been with one job. Dropping the workers stops the others.
+                Ok((_, Err(payload))) => panic::resume_unwind(payload),
+                Err(_) => panic!("a worker thread panicked"),
+            }
+        }
+    }
+}
+
+impl<T> Drop for Workers<T> {
+    fn drop(&mut self) {
+        {
+            let (schedule, wake) = &*self.schedule;
+            if let Ok(mut schedule) = schedule.lock() {
+                schedule.stopped = true;
+            }
+            wake.notify_all();
+        }
+
+        // The channel disconnects once every worker has finished the job it
+        // was running.
+        while self.receiver.recv().is_ok() {}
+    }
+}
+
+/// Calls `job` with every index in `0..count`, as the results are asked
+/// for.
+///
+/// With one job, each index runs on the calling thread when its result is
+/// asked for. Otherwise up to `jobs` worker threads run the index being
+/// asked for and the ones after it, never more than `jobs` ahead. Dropping
+/// the returned iterator early stops the workers from picking up new
+/// indices, and waits for the jobs that are already running. A job that
+/// panics makes the iterator panic in turn when that result is reached.
+pub fn map_ordered<T, F>(count: usize, jobs: usize, job: F) -> OrderedResults<T>
+where
+    T: Send + 'static,
+    F: Fn(usize) -> T + Send + Sync + 'static,
+{
+    OrderedResults {
+        job: Arc::new(job),
+        jobs: jobs.max(1),
+        next: 0,
+        count,
+        workers: None,
+    }
+}
+
+fn start_workers<T: Send + 'static>(job: Job<T>, jobs: usize, count: usize) -> Workers<T> {
+    let (sender, receiver) = mpsc::channel();
+    let schedule = Arc::new((
+        Mutex::new(Schedule {
+            next_index: 0,
+            limit: 0,
+            stopped: false,
+        }),
+        Condvar::new(),
+    ));
+
+    for _ in 0..jobs.min(count) {
+        let sender = sender.clone();
+        let job = Arc::clone(&job);
+        let schedule = Arc::clone(&schedule);
+
+        thread::spawn(move || {
+            while let Some(index) = take_index(&schedule, count) {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| job(index)));
+                let panicked = result.is_err();
+
+                if sender.send((index, result)).is_err() || panicked {
+                    break;
+                }
+            }
+        });
+    }
+
+    Workers {
+        receiver,
+        pending: HashMap::new(),
+        schedule,
+    }
+}
+
+/// Waits until the next index may start and takes it, or returns `None` once
+/// there's nothing left to do.
+fn take_index(schedule: &(Mutex<Schedule>, Condvar), count: usize) -> Option<usize> {
+    let (schedule, wake) = schedule;
+    let mut schedule = schedule.lock().ok()?;
+
+    loop {
+        if schedule.stopped || schedule.next_index >= count {
+            return None;
+        }
+
+        if schedule.next_index < schedule.limit {
+            schedule.next_index += 1;
+            return Some(schedule.next_index - 1);
+        }
+
+        schedule = wake.wait(schedule).ok()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn results_are_in_index_order() {
+        // Later indices finish first.
+        let results: Vec<usize> = map_ordered(8, 4, |index| {
+            thread::sleep(Duration::from_millis(5 * (8 - index) as u64));
+            index
+        })
+        .collect();
+
+        assert_eq!(results, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn workers_never_get_more_than_jobs_ahead() {
+        let highest_started = Arc::new(AtomicUsize::new(0));
+        let started = Arc::clone(&highest_started);
+        let results = map_ordered(20, 3, move |index| {
+            started.fetch_max(index, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+            index
+        });
+
+        for index in results {
+            thread::sleep(Duration::from_millis(5));
+            assert!(highest_started.load(Ordering::SeqCst) < index + 3);
+        }
+    }
+
+    #[test]
+    fn dropping_early_stops_the_workers() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let mut results = map_ordered(100, 4, move |index| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            index
+        });
+
+        assert_eq!(results.next(), Some(0));
+        assert_eq!(results.next(), Some(1));
+        drop(results);
+
+        let after_drop = calls.load(Ordering::SeqCst);
+        assert!(after_drop <= 2 + 4);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(calls.load(Ordering::SeqCst), after_drop);
+    }
+
+    #[test]
+    fn a_panicking_job_panics_the_consumer() {
+        let result = panic::catch_unwind(|| {
+            map_ordered(10, 4, |index| {
+                if index == 3 {
+                    panic!("job failed");
+                }
+                index
+            })
+            .collect::<Vec<_>>()
+        });
+
+        assert!(result.is_err());
+    }
+}
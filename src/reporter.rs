@@ -0,0 +1,471 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ansi_term::{Colour, Style};
+
+use crate::{json, junit, tap};
+use crate::{FileSummary, Test, TestCounts, TestResult, TestStatus};
+
+/// Receives the events of a run as they happen.
+///
+/// Test files run one after the other, and tests are reported in the order
+/// they appear in their file, even when they run in parallel. Every callback
+/// does nothing by default, so a reporter only needs to implement the ones
+/// it cares about. An error returned from a callback stops the run.
+pub trait Reporter {
+    /// Called once every test file has been parsed and validated, with the
+    /// total number of tests across them.
+    fn run_started(&mut self, _files: &[&str], _tests: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called before the first test of a file starts.
+    fn suite_started(&mut self, _file: &str, _tests: &[Test]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called before waiting on the result of a test. `number` starts at 1.
+    fn test_started(&mut self, _file: &str, _number: usize, _test: &Test) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the result of a test is known.
+    fn test_finished(
+        &mut self,
+        _file: &str,
+        _number: usize,
+        _test: &Test,
+        _result: &TestResult,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once every test in a file has finished, the file's `after_all`
+    /// hook has run, and any updates have been written.
+    fn suite_finished(
+        &mut self,
+        _file: &str,
+        _tests: &[Test],
+        _results: &[TestResult],
+        _summary: &FileSummary,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called with problems that don't belong to the result of any one
+    /// test, as they're found.
+    fn notice(&mut self, _notice: &Notice) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once every test file has finished, with the counts for each
+    /// file and in total.
+    fn run_finished(
+        &mut self,
+        _files: &[FileSummary],
+        _counts: &TestCounts,
+        _duration: Duration,
+    ) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub enum Notice {
+    /// A test failed in update mode, but its expectations couldn't be found
+    /// in the test file to rewrite.
+    NotUpdated { file: String, test: String },
+    /// A test is marked `only` on CI.
+    OnlyOnCi { file: String, test: String },
+    /// Temporary directories were kept with `--keep-tmp`.
+    KeptTmp(PathBuf),
+}
+
+impl fmt::Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Notice::NotUpdated { ref file, ref test } => write!(
+                f,
+                "{} couldn't find the expectations of \"{}\" in {} to update them.",
+                Colour::Red.paint("Error:"),
+                test,
+                file
+            ),
+            Notice::OnlyOnCi { ref file, ref test } => write!(
+                f,
+                "{} \"{}\" in {} is marked `only`, which isn't allowed on CI.",
+                Colour::Red.paint("Error:"),
+                test,
+                file
+            ),
+            Notice::KeptTmp(ref dir) => {
+                write!(f, "Kept temporary directories in {}", dir.display())
+            }
+        }
+    }
+}
+
+/// Human-readable progress, with a character per test, followed by a summary
+/// of the failures. When there's more than one test file, the progress and
+/// counts are broken down by file.
+pub struct DotReporter {
+    diff: bool,
+    multiple_files: bool,
+    failures: Vec<String>,
+}
+
+impl DotReporter {
+    /// `diff` shows unexpected output as a diff against the expected output,
+    /// rather than printing both in full.
+    pub fn new(diff: bool) -> DotReporter {
+        DotReporter {
+            diff,
+            multiple_files: false,
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for DotReporter {
+    fn notice(&mut self, notice: &Notice) -> io::Result<()> {
+        eprintln!("{}", notice);
+
+        Ok(())
+    }
+
+    fn run_started(&mut self, files: &[&str], _tests: usize) -> io::Result<()> {
+        self.multiple_files = files.len() > 1;
+
+        Ok(())
+    }
+
+    fn suite_started(&mut self, file: &str, _tests: &[Test]) -> io::Result<()> {
+        if self.multiple_files {
+            print!("{} ", Style::new().bold().paint(file));
+        }
+
+        Ok(())
+    }
+
+    fn test_finished(
+        &mut self,
+        _file: &str,
+        _number: usize,
+        _test: &Test,
+        result: &TestResult,
+    ) -> io::Result<()> {
+        match result.status() {
+            TestStatus::Passed => print!("{}", Colour::Green.paint(".")),
+            TestStatus::Failed => print!("{}", Colour::Red.paint("F")),
+            TestStatus::Updated => print!("{}", Colour::Yellow.paint("U")),
+            TestStatus::Skipped => print!("{}", Colour::Cyan.paint("S")),
+            TestStatus::NotRun => (),
+            TestStatus::Errored => print!("{}", Colour::Red.paint("E")),
+        }
+
+        Ok(())
+    }
+
+    fn suite_finished(
+        &mut self,
+        file: &str,
+        tests: &[Test],
+        results: &[TestResult],
+        summary: &FileSummary,
+    ) -> io::Result<()> {
+        let failed = tests.iter().zip(results).filter(|(_, result)| {
+            matches!(result.status(), TestStatus::Failed | TestStatus::Errored)
+        });
+        let file = if self.multiple_files {
+            Some(file)
+        } else {
+            None
+        };
+
+        // Failures are numbered across every file.
+        for (test, result) in failed {
+            let failure = Failure {
+                file,
+                name: &test.name,
+                failure_number: self.failures.len() + 1,
+                hook_failures: &result.hook_failures,
+                failed_expectations: &result.failed_expectations,
+                diff: self.diff,
+            };
+
+            self.failures.push(failure.to_string());
+        }
+
+        if !summary.hook_failures.is_empty() {
+            let failure = Failure {
+                file,
+                name: "after_all",
+                failure_number: self.failures.len() + 1,
+                hook_failures: &summary.hook_failures,
+                failed_expectations: &[],
+                diff: self.diff,
+            };
+
+            self.failures.push(failure.to_string());
+        }
+
+        if self.multiple_files {
+            println!();
+        }
+
+        Ok(())
+    }
+
+    fn run_finished(
+        &mut self,
+        files: &[FileSummary],
+        counts: &TestCounts,
+        _duration: Duration,
+    ) -> io::Result<()> {
+        if self.multiple_files {
+            print!("\n{}", counts);
+
+            for summary in files {
+                println!("  {}: {}", summary.file, summary.counts.breakdown());
+            }
+        } else {
+            print!("\n\n{}", counts);
+        }
+
+        if !self.failures.is_empty() {
+            print!("\n{}\n\n", Style::new().bold().paint("Failures:"));
+
+            for failure in self.failures.iter() {
+                print!("{}", failure);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Failure<'a> {
+    file: Option<&'a str>,
+    name: &'a str,
+    failure_number: usize,
+    hook_failures: &'a [crate::HookFailure],
+    failed_expectations: &'a [crate::FailedExpectation],
+    diff: bool,
+}
+
+impl<'a> fmt::Display for Failure<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "  {}) ", self.failure_number)?;
+
+        if let Some(file) = self.file {
+            write!(f, "{} ", Style::new().dimmed().paint(format!("{}:", file)))?;
+        }
+
+        write!(f, "{}\n\n", Colour::Red.paint(self.name))?;
+
+        for hook_failure in self.hook_failures {
+            write!(f, "{}", hook_failure)?;
+        }
+
+        for expectation in self.failed_expectations {
+            expectation.fmt_report(f, self.diff)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A JUnit XML report, written once the run is over.
+pub struct JUnitReporter<W: Write> {
+    out: W,
+    diff: bool,
+    suites: Vec<String>,
+}
+
+impl<W: Write> JUnitReporter<W> {
+    /// `diff` shows unexpected output in failure messages as a diff against
+    /// the expected output, rather than both in full.
+    pub fn new(out: W, diff: bool) -> JUnitReporter<W> {
+        JUnitReporter {
+            out,
+            diff,
+            suites: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Reporter for JUnitReporter<W> {
+    fn notice(&mut self, notice: &Notice) -> io::Result<()> {
+        eprintln!("{}", notice);
+
+        Ok(())
+    }
+
+    fn suite_finished(
+        &mut self,
+        file: &str,
+        tests: &[Test],
+        results: &[TestResult],
+        summary: &FileSummary,
+    ) -> io::Result<()> {
+        let suite = junit::render_suite(file, tests, results, summary, self.diff);
+
+        self.suites.push(suite);
+
+        Ok(())
+    }
+
+    fn run_finished(
+        &mut self,
+        files: &[FileSummary],
+        counts: &TestCounts,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let report = junit::render(&self.suites, files, counts, duration);
+
+        self.out.write_all(report.as_bytes())?;
+        self.out.flush()
+    }
+}
+
+/// A TAP (Test Anything Protocol) stream. Tests from every file are
+/// numbered in a single stream, with a comment naming each file when there's
+/// more than one.
+pub struct TapReporter<W: Write> {
+    out: W,
+    multiple_files: bool,
+    count: usize,
+}
+
+impl<W: Write> TapReporter<W> {
+    pub fn new(out: W) -> TapReporter<W> {
+        TapReporter {
+            out,
+            multiple_files: false,
+            count: 0,
+        }
+    }
+}
+
+impl<W: Write> Reporter for TapReporter<W> {
+    fn notice(&mut self, notice: &Notice) -> io::Result<()> {
+        eprintln!("{}", notice);
+
+        Ok(())
+    }
+
+    fn run_started(&mut self, files: &[&str], tests: usize) -> io::Result<()> {
+        self.multiple_files = files.len() > 1;
+
+        self.out.write_all(tap::header(tests).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn suite_started(&mut self, file: &str, _tests: &[Test]) -> io::Result<()> {
+        if self.multiple_files {
+            self.out.write_all(tap::comment(file).as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn test_finished(
+        &mut self,
+        _file: &str,
+        _number: usize,
+        test: &Test,
+        result: &TestResult,
+    ) -> io::Result<()> {
+        self.count += 1;
+
+        self.out
+            .write_all(tap::test_line(self.count, test, result).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn suite_finished(
+        &mut self,
+        file: &str,
+        _tests: &[Test],
+        _results: &[TestResult],
+        summary: &FileSummary,
+    ) -> io::Result<()> {
+        self.out
+            .write_all(tap::after_all_comments(file, &summary.hook_failures).as_bytes())?;
+        self.out.flush()
+    }
+}
+
+/// A stream of JSON events, one per line.
+pub struct JsonReporter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(out: W) -> JsonReporter<W> {
+        JsonReporter { out }
+    }
+}
+
+impl<W: Write> Reporter for JsonReporter<W> {
+    fn notice(&mut self, notice: &Notice) -> io::Result<()> {
+        eprintln!("{}", notice);
+
+        Ok(())
+    }
+
+    fn run_started(&mut self, files: &[&str], tests: usize) -> io::Result<()> {
+        self.out
+            .write_all(json::run_started(files, tests).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn suite_started(&mut self, file: &str, tests: &[Test]) -> io::Result<()> {
+        self.out
+            .write_all(json::suite_started(file, tests.len()).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn test_started(&mut self, file: &str, number: usize, test: &Test) -> io::Result<()> {
+        self.out
+            .write_all(json::test_started(file, number, test).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn test_finished(
+        &mut self,
+        file: &str,
+        number: usize,
+        test: &Test,
+        result: &TestResult,
+    ) -> io::Result<()> {
+        self.out
+            .write_all(json::test_finished(file, number, test, result).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn suite_finished(
+        &mut self,
+        file: &str,
+        _tests: &[Test],
+        _results: &[TestResult],
+        summary: &FileSummary,
+    ) -> io::Result<()> {
+        self.out
+            .write_all(json::suite_finished(file, summary).as_bytes())?;
+        self.out.flush()
+    }
+
+    fn run_finished(
+        &mut self,
+        _files: &[FileSummary],
+        counts: &TestCounts,
+        duration: Duration,
+    ) -> io::Result<()> {
+        self.out
+            .write_all(json::run_finished(duration, counts).as_bytes())?;
+        self.out.flush()
+    }
+}
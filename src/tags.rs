@@ -0,0 +1,290 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+/// A boolean expression over tags, for `--tags` and `--skip-tags`, such as
+/// `smoke and not (slow or network)`.
+///
+/// `not` binds tighter than `and`, which binds tighter than `or`.
+#[derive(Clone, Debug)]
+pub struct TagExpression(Node);
+
+#[derive(Clone, Debug)]
+enum Node {
+    Tag(String),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+#[derive(Debug)]
+pub struct TagExpressionError(String);
+
+impl fmt::Display for TagExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Tag(ref tag) => write!(f, "\"{}\"", tag),
+            Token::And => write!(f, "\"and\""),
+            Token::Or => write!(f, "\"or\""),
+            Token::Not => write!(f, "\"not\""),
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\""),
+        }
+    }
+}
+
+impl TagExpression {
+    pub fn matches(&self, tags: &[String]) -> bool {
+        self.0.matches(tags)
+    }
+}
+
+impl Node {
+    fn matches(&self, tags: &[String]) -> bool {
+        match *self {
+            Node::Tag(ref tag) => tags.contains(tag),
+            Node::Not(ref node) => !node.matches(tags),
+            Node::And(ref left, ref right) => left.matches(tags) && right.matches(tags),
+            Node::Or(ref left, ref right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+impl FromStr for TagExpression {
+    type Err = TagExpressionError;
+
+    fn from_str(expression: &str) -> Result<TagExpression, TagExpressionError> {
+        let mut tokens = tokenize(expression)?.into_iter().peekable();
+        let node = parse_or(&mut tokens)?;
+
+        match tokens.next() {
+            None => Ok(TagExpression(node)),
+            Some(token) => Err(TagExpressionError(format!("unexpected {}", token))),
+        }
+    }
+}
+
+/// Whether a tag can be used in a tag expression.
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.chars().all(is_tag_char) && !matches!(tag, "and" | "or" | "not")
+}
+
+/// Whether a test with `tags` should run given the `--tags` and
+/// `--skip-tags` expressions.
+pub fn is_selected(
+    tags: &[String],
+    include: Option<&TagExpression>,
+    skip: Option<&TagExpression>,
+) -> bool {
+    let included = include.map_or(true, |expression| expression.matches(tags));
+    let skipped = skip.map_or(false, |expression| expression.matches(tags));
+
+    included && !skipped
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/')
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, TagExpressionError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::Open);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::Close);
+        } else if is_tag_char(c) {
+            let mut word = String::new();
+
+            while let Some(&c) = chars.peek() {
+                if !is_tag_char(c) {
+                    break;
+                }
+
+                word.push(c);
+                chars.next();
+            }
+
+            tokens.push(match word.as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Tag(word),
+            });
+        } else {
+            return Err(TagExpressionError(format!("unexpected character '{}'", c)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Node, TagExpressionError> {
+    let mut node = parse_and(tokens)?;
+
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        node = Node::Or(Box::new(node), Box::new(parse_and(tokens)?));
+    }
+
+    Ok(node)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Node, TagExpressionError> {
+    let mut node = parse_not(tokens)?;
+
+    while tokens.peek() == Some(&Token::And) {
+        tokens.next();
+        node = Node::And(Box::new(node), Box::new(parse_not(tokens)?));
+    }
+
+    Ok(node)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Node, TagExpressionError> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Node::Not(Box::new(parse_not(tokens)?))),
+        Some(Token::Tag(tag)) => Ok(Node::Tag(tag)),
+        Some(Token::Open) => {
+            let node = parse_or(tokens)?;
+
+            match tokens.next() {
+                Some(Token::Close) => Ok(node),
+                Some(token) => Err(TagExpressionError(format!(
+                    "expected \")\" but found {}",
+                    token
+                ))),
+                None => Err(TagExpressionError(String::from("missing \")\""))),
+            }
+        }
+        Some(token) => Err(TagExpressionError(format!(
+            "expected a tag but found {}",
+            token
+        ))),
+        None => Err(TagExpressionError(String::from(
+            "expected a tag but the expression ended",
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expression: &str, tags: &[&str]) -> bool {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        expression.parse::<TagExpression>().unwrap().matches(&tags)
+    }
+
+    fn error(expression: &str) -> String {
+        expression.parse::<TagExpression>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn single_tag() {
+        assert!(matches("smoke", &["slow", "smoke"]));
+        assert!(!matches("smoke", &["slow"]));
+        assert!(!matches("smoke", &[]));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // (a or b) and c wouldn't match.
+        assert!(matches("a or b and c", &["a"]));
+        // a or (b and c) would match.
+        assert!(!matches("a and b or c", &["a"]));
+        assert!(matches("a and b or c", &["c"]));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        // not (a and b) would match.
+        assert!(!matches("not a and b", &[]));
+        assert!(matches("not a and b", &["b"]));
+        assert!(matches("not not a", &["a"]));
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert!(!matches("(a or b) and c", &["a"]));
+        assert!(matches("not (a and b)", &[]));
+        assert!(matches("smoke and not (slow or network)", &["smoke"]));
+        assert!(!matches(
+            "smoke and not (slow or network)",
+            &["smoke", "network"]
+        ));
+    }
+
+    #[test]
+    fn chained_operators() {
+        assert!(matches("a and b and c or d", &["d"]));
+        assert!(matches("a or b or c", &["c"]));
+    }
+
+    #[test]
+    fn tags_can_contain_punctuation() {
+        assert!(matches(
+            "os:linux and not area/ui",
+            &["os:linux", "area/core"]
+        ));
+        assert!(matches("v1.2-beta_x", &["v1.2-beta_x"]));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert_eq!(error(""), "expected a tag but the expression ended");
+        assert_eq!(error("a and"), "expected a tag but the expression ended");
+        assert_eq!(error("a or or b"), "expected a tag but found \"or\"");
+        assert_eq!(error("a b"), "unexpected \"b\"");
+        assert_eq!(error("(a or b"), "missing \")\"");
+        assert_eq!(error("(a b)"), "expected \")\" but found \"b\"");
+        assert_eq!(error("a)"), "unexpected \")\"");
+        assert_eq!(error("a & b"), "unexpected character '&'");
+    }
+
+    #[test]
+    fn keywords_are_not_valid_tags() {
+        assert!(is_valid_tag("smoke"));
+        assert!(!is_valid_tag("and"));
+        assert!(!is_valid_tag("not"));
+        assert!(!is_valid_tag(""));
+        assert!(!is_valid_tag("a b"));
+    }
+
+    #[test]
+    fn skip_tags_win() {
+        let smoke: TagExpression = "smoke".parse().unwrap();
+        let slow: TagExpression = "slow".parse().unwrap();
+        let tags = vec![String::from("smoke"), String::from("slow")];
+
+        assert!(is_selected(&tags, Some(&smoke), None));
+        assert!(!is_selected(&tags, Some(&smoke), Some(&slow)));
+        assert!(!is_selected(&tags, None, Some(&slow)));
+        assert!(is_selected(&[], None, None));
+    }
+}
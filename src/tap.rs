@@ -0,0 +1,108 @@
+use crate::expectations::{Detail, Failure};
+use crate::hooks::HookFailure;
+use crate::yaml;
+use crate::TestStatus;
+
+/// The version line and plan that start a TAP stream.
+pub fn header(count: usize) -> String {
+    format!("TAP version 13\n1..{}\n", count)
+}
+
+/// A comment line, which TAP consumers ignore.
+pub fn comment(text: &str) -> String {
+    format!("# {}\n", text.replace('\n', " "))
+}
+
+/// Comments describing a failed `after_all` hook. TAP has no way to report
+/// a failure outside of a test, so these are only informational.
+pub fn after_all_comments(file: &str, hook_failures: &[HookFailure]) -> String {
+    hook_failures
+        .iter()
+        .map(|hook_failure| comment(&format!("{}: {}", file, hook_failure.summary())))
+        .collect()
+}
+
+/// The result line for a test, followed by a YAML diagnostic block that
+/// describes each of its failures.
+pub fn test_line(number: usize, test: &super::Test, result: &super::TestResult) -> String {
+    // `#` starts a directive, and a description can't span lines.
+    let description = test.name.replace('#', "\\#").replace('\n', " ");
+
+    if result.not_run {
+        return format!("ok {} - {} # SKIP not run\n", number, description);
+    }
+
+    if result.skipped {
+        return match test.skip_reason {
+            Some(ref reason) => format!(
+                "ok {} - {} # SKIP {}\n",
+                number,
+                description,
+                reason.replace('\n', " ")
+            ),
+            None => format!("ok {} - {} # SKIP\n", number, description),
+        };
+    }
+
+    match result.status() {
+        TestStatus::Passed => return format!("ok {} - {}\n", number, description),
+        TestStatus::Updated => return format!("ok {} - {} (updated)\n", number, description),
+        _ => (),
+    }
+
+    let mut line = format!("not ok {} - {}\n", number, description);
+
+    line.push_str("  ---\n");
+    line.push_str("  failures:\n");
+
+    let failures = result
+        .hook_failures
+        .iter()
+        .map(|hook_failure| hook_failure as &dyn Failure)
+        .chain(
+            result
+                .failed_expectations
+                .iter()
+                .map(|expectation| expectation as &dyn Failure),
+        );
+
+    for failure in failures {
+        push_field(
+            &mut line,
+            "    - ",
+            "type",
+            &Detail::Text(failure.kind().to_string()),
+        );
+        push_field(
+            &mut line,
+            "      ",
+            "message",
+            &Detail::Text(failure.summary()),
+        );
+
+        for (name, value) in failure.details() {
+            push_field(&mut line, "      ", name, &value);
+        }
+    }
+
+    line.push_str("  ...\n");
+    line
+}
+
+fn push_field(line: &mut String, prefix: &str, name: &str, value: &Detail) {
+    let rendered = match *value {
+        Detail::Text(ref text) => yaml::render_string(text),
+        Detail::Integer(integer) => vec![integer.to_string()],
+        Detail::Seconds(seconds) => vec![seconds.to_string()],
+    };
+
+    line.push_str(&format!("{}{}: {}\n", prefix, name, rendered[0]));
+
+    for value_line in &rendered[1..] {
+        if value_line.is_empty() {
+            line.push('\n');
+        } else {
+            line.push_str(&format!("{}  {}\n", " ".repeat(prefix.len()), value_line));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The random part of the name of `root()`, or 0 until it's been created.
+static ROOT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A fresh directory for a single test, removed when it's dropped unless it's
+/// kept.
+pub struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    /// Creates a directory for the test named `name`, under `root()`. The
+    /// directory is named after the test so that kept directories are easy
+    /// to find.
+    pub fn create(name: &str, keep: bool) -> io::Result<TempDir> {
+        let root = create_root()?;
+
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+            let path = root.join(format!("{}-{}", id, slug(name)));
+
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path, keep }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            // There's nowhere to report a failure from here, and a leftover
+            // directory in the system's temporary directory is harmless.
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// The directory that every test's temporary directory is created in, if
+/// it has been created. It's unique to this process.
+pub fn root() -> Option<PathBuf> {
+    match ROOT_ID.load(Ordering::SeqCst) {
+        0 => None,
+        id => Some(root_path(id)),
+    }
+}
+
+/// Removes `root()` if it's empty, which it is unless directories were kept.
+pub fn remove_root() {
+    if let Some(root) = root() {
+        let _ = fs::remove_dir(root);
+    }
+}
+
+/// Creates `root()` if it doesn't exist yet. The name is random and the
+/// directory must not exist already, so that nobody else sharing the
+/// system's temporary directory can set it up in advance. On Unix it's only
+/// accessible to the current user.
+fn create_root() -> io::Result<PathBuf> {
+    if let Some(root) = root() {
+        return Ok(root);
+    }
+
+    loop {
+        let id = RandomState::new().build_hasher().finish().max(1);
+        let path = root_path(id);
+
+        match create_private_dir(&path) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+
+        // Another thread may have created a root at the same time, in which
+        // case only one of them is kept.
+        return match ROOT_ID.compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => Ok(path),
+            Err(winner) => {
+                let _ = fs::remove_dir(&path);
+                Ok(root_path(winner))
+            }
+        };
+    }
+}
+
+fn root_path(id: u64) -> PathBuf {
+    env::temp_dir().join(format!("cli_test-{}-{:016x}", process::id(), id))
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> io::Result<()> {
+    fs::create_dir(path)
+}
+
+/// The test name with anything but letters and digits replaced by `-`, and
+/// cut short so that it stays a reasonable file name.
+fn slug(name: &str) -> String {
+    name.chars()
+        .take(40)
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_is_created_once_and_private() {
+        let first = TempDir::create("a test", false).unwrap();
+        let second = TempDir::create("a test", false).unwrap();
+        let root = root().unwrap();
+
+        assert_eq!(first.path().parent(), Some(root.as_path()));
+        assert_eq!(second.path().parent(), Some(root.as_path()));
+        assert_ne!(first.path(), second.path());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(&root).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        drop((first, second));
+        remove_root();
+    }
+
+    #[test]
+    fn slug_replaces_punctuation() {
+        assert_eq!(slug("runs `ls` -l"), "runs--ls---l");
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+/// How a file in a directory produced by a test differs from the same file
+/// in its golden directory.
+#[derive(Clone, Debug)]
+pub enum TreeChange {
+    /// The file isn't in the golden directory. `mode` is only set when
+    /// permissions are compared.
+    Added {
+        contents: Vec<u8>,
+        mode: Option<u32>,
+    },
+    /// The file is only in the golden directory.
+    Removed,
+    /// The file's contents differ.
+    Changed { expected: Vec<u8>, actual: Vec<u8> },
+    /// The file's permission bits differ.
+    Mode { expected: u32, actual: u32 },
+}
+
+/// Compares every file under `actual` with the file at the same path under
+/// `golden`, returning the differences by path relative to either directory.
+///
+/// Only files are compared, so empty directories and symlinks are ignored. A directory
+/// that doesn't exist is treated as empty. Permissions are only compared on
+/// Unix.
+pub fn compare(
+    actual: &Path,
+    golden: &Path,
+    permissions: bool,
+) -> io::Result<Vec<(String, TreeChange)>> {
+    let actual_files = files(actual)?;
+    let golden_files = files(golden)?;
+
+    let mut changes: Vec<(String, TreeChange)> = Vec::new();
+
+    for name in golden_files.keys() {
+        if !actual_files.contains_key(name) {
+            changes.push((name.clone(), TreeChange::Removed));
+        }
+    }
+
+    for (name, path) in &actual_files {
+        let golden_path = match golden_files.get(name) {
+            Some(golden_path) => golden_path,
+            None => {
+                changes.push((
+                    name.clone(),
+                    TreeChange::Added {
+                        contents: fs::read(path)?,
+                        mode: if permissions { mode(path)? } else { None },
+                    },
+                ));
+                continue;
+            }
+        };
+
+        let expected = fs::read(golden_path)?;
+        let contents = fs::read(path)?;
+
+        if contents != expected {
+            changes.push((
+                name.clone(),
+                TreeChange::Changed {
+                    expected,
+                    actual: contents,
+                },
+            ));
+        }
+
+        if permissions {
+            if let (Some(expected), Some(actual)) = (mode(golden_path)?, mode(path)?) {
+                if expected != actual {
+                    changes.push((name.clone(), TreeChange::Mode { expected, actual }));
+                }
+            }
+        }
+    }
+
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(changes)
+}
+
+/// Every file under `dir`, by its path relative to `dir` with `/` between
+/// components.
+fn files(dir: &Path) -> io::Result<BTreeMap<String, PathBuf>> {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    if dir.is_dir() {
+        collect(dir, "", &mut files)?;
+    }
+
+    Ok(files)
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut BTreeMap<String, PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        let name = format!(
+            "{}{}",
+            prefix,
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+
+        // Symlinks are skipped rather than followed, since following them
+        // could leave the tree or loop forever.
+        if file_type.is_dir() {
+            collect(&path, &format!("{}/", name), files)?;
+        } else if !file_type.is_symlink() {
+            files.insert(name, path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The permission bits of the file at `path`, where they're supported.
+#[cfg(unix)]
+pub fn mode(path: &Path) -> io::Result<Option<u32>> {
+    Ok(Some(fs::metadata(path)?.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+pub fn mode(_path: &Path) -> io::Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Sets the permission bits of the file at `path`, where they're supported.
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_skipped() {
+        let dir = env::temp_dir().join(format!("cli_test-tree-{}", std::process::id()));
+        fs::create_dir_all(dir.join("actual/sub")).unwrap();
+        fs::create_dir_all(dir.join("golden/sub")).unwrap();
+        fs::write(dir.join("actual/sub/file"), "a").unwrap();
+        fs::write(dir.join("golden/sub/file"), "a").unwrap();
+        std::os::unix::fs::symlink(".", dir.join("actual/sub/loop")).unwrap();
+        std::os::unix::fs::symlink("file", dir.join("golden/sub/link")).unwrap();
+
+        let changes = compare(&dir.join("actual"), &dir.join("golden"), false);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(changes.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,475 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::tree;
+use crate::yaml;
+
+/// New expected values for a test that failed in update mode. Only the
+/// fields that are set get rewritten.
+pub struct Update {
+    pub name: String,
+    pub out: Option<String>,
+    pub err: Option<String>,
+    pub exit_code: Option<i32>,
+    /// Changes to golden files outside the test file.
+    pub files: Vec<FileUpdate>,
+}
+
+impl Update {
+    /// Whether any values in the test file itself need rewriting.
+    fn has_values(&self) -> bool {
+        self.out.is_some() || self.err.is_some() || self.exit_code.is_some()
+    }
+}
+
+/// A change to a golden file, by its path relative to the test file.
+pub enum FileUpdate {
+    Write(PathBuf, Vec<u8>),
+    Remove(PathBuf),
+    SetMode(PathBuf, u32),
+}
+
+/// A test within the test file, as a range of lines.
+struct Item {
+    lines: Range<usize>,
+    key_indent: usize,
+}
+
+/// A `key: value` pair within a test, as a range of lines. Comments and
+/// blank lines that follow the value aren't included.
+struct Key<'a> {
+    name: &'a str,
+    lines: Range<usize>,
+}
+
+/// Rewrites the expectations of the given tests in place.
+///
+/// The file is edited as text rather than re-serialized, so comments,
+/// ordering and formatting outside the rewritten values are left untouched.
+/// Returns the names of tests that couldn't be rewritten because they, or
+/// the values to rewrite, couldn't be located (flow-style mappings aren't
+/// supported, for example).
+pub fn rewrite(filename: &str, updates: &[Update]) -> io::Result<Vec<String>> {
+    let base_dir = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+
+    for update in updates {
+        for file in &update.files {
+            apply_file(base_dir, file)?;
+        }
+    }
+
+    let contents = fs::read_to_string(filename)?;
+    let (contents, not_updated) = apply(&contents, updates);
+
+    fs::write(filename, contents)?;
+
+    Ok(not_updated)
+}
+
+/// Whether the values of `update` can be located in `contents`, the text of
+/// the test file, so that `rewrite` would change them.
+pub fn can_apply(contents: &str, update: &Update) -> bool {
+    !update.has_values() || apply(contents, std::slice::from_ref(update)).1.is_empty()
+}
+
+fn apply(contents: &str, updates: &[Update]) -> (String, Vec<String>) {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let items = items(&lines);
+
+    let mut edits: Vec<(Range<usize>, Vec<String>)> = Vec::new();
+    let mut not_updated: Vec<String> = Vec::new();
+
+    for update in updates.iter().filter(|update| update.has_values()) {
+        let item_edits = items
+            .iter()
+            .find(|item| item_name(&lines, item).as_deref() == Some(update.name.as_str()))
+            .and_then(|item| edits_for(&lines, item, update));
+
+        match item_edits {
+            Some(item_edits) => edits.extend(item_edits),
+            None => not_updated.push(update.name.clone()),
+        }
+    }
+
+    // Apply edits from the bottom up so earlier line numbers stay valid.
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+    let mut lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    for (range, replacement) in edits {
+        lines.splice(range, replacement);
+    }
+
+    (lines.concat(), not_updated)
+}
+
+fn apply_file(base_dir: &Path, file: &FileUpdate) -> io::Result<()> {
+    match *file {
+        FileUpdate::Write(ref path, ref contents) => {
+            let path = base_dir.join(path);
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(path, contents)
+        }
+        FileUpdate::Remove(ref path) => fs::remove_file(base_dir.join(path)),
+        FileUpdate::SetMode(ref path, mode) => tree::set_mode(&base_dir.join(path), mode),
+    }
+}
+
+fn edits_for(
+    lines: &[&str],
+    item: &Item,
+    update: &Update,
+) -> Option<Vec<(Range<usize>, Vec<String>)>> {
+    let keys = keys(lines, item);
+    let mut edits: Vec<(Range<usize>, Vec<String>)> = Vec::new();
+
+    let values = [
+        ("out", update.out.as_deref().map(yaml::render_string)),
+        ("err", update.err.as_deref().map(yaml::render_string)),
+        (
+            "exit_code",
+            update.exit_code.map(|code| vec![code.to_string()]),
+        ),
+    ];
+
+    for (name, value) in values.iter() {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let key = keys.iter().find(|key| key.name == *name)?;
+        let first_line = lines[key.lines.start];
+        let prefix =
+            &first_line[..first_line.len() - first_line.trim_start_matches(&[' ', '-'][..]).len()];
+        let value_indent = " ".repeat(item.key_indent + 2);
+
+        let mut replacement = vec![format!("{}{}: {}\n", prefix, name, value[0])];
+        for line in &value[1..] {
+            if line.is_empty() {
+                replacement.push(String::from("\n"));
+            } else {
+                replacement.push(format!("{}{}\n", value_indent, line));
+            }
+        }
+
+        edits.push((key.lines.clone(), replacement));
+    }
+
+    Some(edits)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_sequence_entry(line: &str) -> bool {
+    let content = line.trim();
+    content == "-" || content.starts_with("- ")
+}
+
+/// The lines that hold the list of tests: the whole file for a plain list,
+/// or the lines after `tests:` for a file with file-level settings.
+fn tests_region(lines: &[&str]) -> Range<usize> {
+    let first = lines
+        .iter()
+        .position(|line| !is_blank_or_comment(line) && !line.starts_with("---"));
+
+    match first {
+        Some(index) if is_sequence_entry(lines[index]) => index..lines.len(),
+        _ => {
+            let tests_key = lines.iter().position(|line| {
+                line.starts_with("tests:") && is_blank_or_comment(&line["tests:".len()..])
+            });
+
+            match tests_key {
+                Some(index) => index + 1..lines.len(),
+                None => 0..0,
+            }
+        }
+    }
+}
+
+/// Finds the tests in the file by looking for sequence entries at the
+/// outermost indentation of the list of tests.
+fn items(lines: &[&str]) -> Vec<Item> {
+    let region = tests_region(lines);
+    let mut starts: Vec<usize> = Vec::new();
+    let mut sequence_indent: Option<usize> = None;
+    let mut end = region.end;
+
+    for index in region {
+        let line = lines[index];
+        if is_blank_or_comment(line) {
+            continue;
+        }
+
+        let line_indent = indent(line);
+        let sequence_indent = *sequence_indent.get_or_insert(line_indent);
+
+        if line_indent < sequence_indent
+            || (line_indent == sequence_indent && !is_sequence_entry(line))
+        {
+            end = index;
+            break;
+        }
+
+        if line_indent == sequence_indent {
+            starts.push(index);
+        }
+    }
+
+    let ends = starts.iter().skip(1).copied().chain(std::iter::once(end));
+
+    starts
+        .iter()
+        .zip(ends)
+        .filter_map(|(&start, end)| {
+            let dash_line = lines[start];
+            let after_dash = &dash_line[indent(dash_line) + 1..];
+
+            // Keys either start on the same line as the dash or on the
+            // following lines.
+            let key_indent = if after_dash.trim().is_empty() {
+                lines[start + 1..end]
+                    .iter()
+                    .find(|line| !is_blank_or_comment(line))
+                    .map(|line| indent(line))?
+            } else {
+                dash_line.len() - after_dash.trim_start_matches(' ').len()
+            };
+
+            Some(Item {
+                lines: start..end,
+                key_indent,
+            })
+        })
+        .collect()
+}
+
+fn keys<'a>(lines: &[&'a str], item: &Item) -> Vec<Key<'a>> {
+    let mut keys: Vec<(&'a str, usize)> = Vec::new();
+
+    for index in item.lines.clone() {
+        let line = lines[index];
+        if is_blank_or_comment(line) || line.len() <= item.key_indent {
+            continue;
+        }
+
+        let is_key_line = indent(line) == item.key_indent
+            || (index == item.lines.start && indent(line) < item.key_indent);
+        if !is_key_line {
+            continue;
+        }
+
+        let content = &line[item.key_indent..];
+        let name_end = content
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(content.len());
+        let after_name = &content[name_end..];
+
+        if name_end > 0 && (after_name.starts_with(": ") || after_name.trim_end() == ":") {
+            keys.push((&content[..name_end], index));
+        }
+    }
+
+    let ends = keys
+        .iter()
+        .skip(1)
+        .map(|&(_, index)| index)
+        .chain(std::iter::once(item.lines.end));
+
+    keys.iter()
+        .zip(ends)
+        .map(|(&(name, start), mut end)| {
+            while end > start + 1 && is_trailing_filler(lines[end - 1], item.key_indent) {
+                end -= 1;
+            }
+
+            Key {
+                name,
+                lines: start..end,
+            }
+        })
+        .collect()
+}
+
+/// Blank lines and comments that aren't indented past the key belong
+/// between keys rather than to the value before them.
+fn is_trailing_filler(line: &str, key_indent: usize) -> bool {
+    line.trim().is_empty() || (line.trim_start().starts_with('#') && indent(line) <= key_indent)
+}
+
+fn item_name(lines: &[&str], item: &Item) -> Option<String> {
+    let key = keys(lines, item)
+        .into_iter()
+        .find(|key| key.name == "test")?;
+
+    // Parse the key and its value on their own, with the indentation
+    // removed, to handle quoting and multi-line names.
+    let text: String = lines[key.lines]
+        .iter()
+        .map(|line| line.get(item.key_indent..).unwrap_or("\n"))
+        .collect();
+    let mut mapping: BTreeMap<String, String> = serde_yaml::from_str(&text).ok()?;
+
+    mapping.remove("test")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(contents: &str) -> Vec<&str> {
+        contents.split_inclusive('\n').collect()
+    }
+
+    fn update(name: &str) -> Update {
+        Update {
+            name: String::from(name),
+            out: None,
+            err: None,
+            exit_code: None,
+            files: Vec::new(),
+        }
+    }
+
+    fn s(line: &str) -> String {
+        String::from(line)
+    }
+
+    #[test]
+    fn tests_region_of_a_plain_list() {
+        let lines = lines("# Tests\n---\n- test: a\n  in: echo\n");
+        assert_eq!(tests_region(&lines), 2..4);
+    }
+
+    #[test]
+    fn tests_region_of_a_tests_mapping() {
+        let lines = lines("timeout: 5\ntests: # all of them\n  - test: a\n    in: echo\n");
+        assert_eq!(tests_region(&lines), 2..4);
+    }
+
+    #[test]
+    fn tests_region_without_tests() {
+        let lines = lines("timeout: 5\n");
+        assert_eq!(tests_region(&lines), 0..0);
+    }
+
+    #[test]
+    fn items_with_keys_on_the_dash_line() {
+        let lines = lines("- test: a\n  in: echo a\n\n- test: b\n  in: echo b\n");
+        let items = items(&lines);
+
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[0].lines.clone(), items[0].key_indent), (0..3, 2));
+        assert_eq!((items[1].lines.clone(), items[1].key_indent), (3..5, 2));
+    }
+
+    #[test]
+    fn items_with_keys_after_the_dash_line() {
+        let lines = lines("tests:\n  -\n    test: a\n    in: echo a\nafter: 1\n");
+        let items = items(&lines);
+
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].lines.clone(), items[0].key_indent), (1..4, 4));
+    }
+
+    #[test]
+    fn keys_leave_out_trailing_comments() {
+        let lines = lines(
+            "- test: a\n  out: |\n    a\n    # still output\n\n  # exit_code: 1\n  in: echo a\n",
+        );
+        let items = items(&lines);
+        let keys: Vec<(&str, Range<usize>)> = keys(&lines, &items[0])
+            .into_iter()
+            .map(|key| (key.name, key.lines))
+            .collect();
+
+        assert_eq!(keys, vec![("test", 0..1), ("out", 1..4), ("in", 6..7)]);
+    }
+
+    #[test]
+    fn edits_for_writes_blocks_and_quoted_strings() {
+        let lines = lines("- out: old\n  err: |\n    old\n  exit_code: 0\n  test: a\n");
+        let items = items(&lines);
+        let mut update = update("a");
+        update.out = Some(String::from("new\nlines\n"));
+        update.err = Some(String::from("new"));
+        update.exit_code = Some(1);
+
+        let edits = edits_for(&lines, &items[0], &update).unwrap();
+
+        assert_eq!(
+            edits,
+            vec![
+                (
+                    0..1,
+                    vec![s("- out: |\n"), s("    new\n"), s("    lines\n")]
+                ),
+                (1..3, vec![s("  err: \"new\"\n")]),
+                (3..4, vec![s("  exit_code: 1\n")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn edits_for_needs_the_key_to_exist() {
+        let lines = lines("- test: a\n  in: echo a\n");
+        let items = items(&lines);
+        let mut update = update("a");
+        update.out = Some(String::from("a\n"));
+
+        assert!(edits_for(&lines, &items[0], &update).is_none());
+    }
+
+    #[test]
+    fn apply_to_a_tests_mapping() {
+        let contents = "timeout: 5\ntests:\n  - test: a\n    out: old # was\n    in: echo new\n";
+        let mut update = update("a");
+        update.out = Some(String::from("new\n"));
+
+        let (contents, not_updated) = apply(contents, &[update]);
+
+        assert_eq!(
+            contents,
+            "timeout: 5\ntests:\n  - test: a\n    out: |\n      new\n    in: echo new\n"
+        );
+        assert!(not_updated.is_empty());
+    }
+
+    #[test]
+    fn can_apply_needs_the_values_to_be_located() {
+        let contents = "- {test: a, in: echo a, out: b}\n";
+        assert!(can_apply(contents, &update("a")));
+
+        let mut update = update("a");
+        update.out = Some(String::from("a\n"));
+        assert!(!can_apply(contents, &update));
+    }
+
+    #[test]
+    fn flow_mappings_are_not_updated() {
+        let contents = "- {test: a, in: echo a, out: b}\n";
+        let mut update = update("a");
+        update.out = Some(String::from("a\n"));
+
+        let (new_contents, not_updated) = apply(contents, &[update]);
+
+        assert_eq!(new_contents, contents);
+        assert_eq!(not_updated, vec!["a"]);
+    }
+}
@@ -0,0 +1,114 @@
+/// Renders a string as the lines of a YAML value: the text following the
+/// key on the first line, and any further (unindented) lines.
+///
+/// Multi-line output is written as a literal block scalar when it can be
+/// represented as one exactly, and as a double-quoted string otherwise.
+pub fn render_string(value: &str) -> Vec<String> {
+    match block_indicator(value) {
+        Some(indicator) => {
+            let content = value.strip_suffix('\n').unwrap_or(value);
+
+            std::iter::once(String::from(indicator))
+                .chain(content.split('\n').map(String::from))
+                .collect()
+        }
+        None => vec![quote(value)],
+    }
+}
+
+/// The block scalar header to use for `value`, if it can be written as a
+/// block scalar without changing its contents.
+fn block_indicator(value: &str) -> Option<&'static str> {
+    if !value.contains('\n') {
+        return None;
+    }
+
+    let (content, indicator) = match value.strip_suffix('\n') {
+        // More than one trailing newline would need the "keep" indicator,
+        // which would also swallow any blank lines after the value.
+        Some(content) if content.ends_with('\n') => return None,
+        Some(content) => (content, "|"),
+        None => (value, "|-"),
+    };
+
+    let first_line = content.split('\n').find(|line| !line.is_empty())?;
+    let has_special_chars = content.chars().any(|c| {
+        (c.is_control() && c != '\n' && c != '\t')
+            || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}')
+    });
+    // Indentation is what delimits a block scalar, so leading whitespace on
+    // the first line and whitespace-only lines can't be represented reliably.
+    let has_ambiguous_whitespace = first_line.starts_with(&[' ', '\t'][..])
+        || content
+            .split('\n')
+            .any(|line| !line.is_empty() && line.trim().is_empty());
+
+    if has_special_chars || has_ambiguous_whitespace {
+        None
+    } else {
+        Some(indicator)
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}') => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_line_is_quoted() {
+        assert_eq!(render_string("hello"), vec!["\"hello\""]);
+        assert_eq!(render_string(""), vec!["\"\""]);
+        assert_eq!(render_string("say \"hi\"\\"), vec![r#""say \"hi\"\\""#]);
+    }
+
+    #[test]
+    fn multi_line_is_a_block() {
+        assert_eq!(render_string("a\nb\n"), vec!["|", "a", "b"]);
+        assert_eq!(render_string("a\n\nb\n"), vec!["|", "a", "", "b"]);
+    }
+
+    #[test]
+    fn missing_trailing_newline_is_stripped() {
+        assert_eq!(render_string("a\nb"), vec!["|-", "a", "b"]);
+    }
+
+    #[test]
+    fn unrepresentable_blocks_are_quoted() {
+        assert_eq!(render_string("a\n\n"), vec![r#""a\n\n""#]);
+        assert_eq!(render_string(" a\nb\n"), vec![r#"" a\nb\n""#]);
+        assert_eq!(render_string("a\n  \nb\n"), vec![r#""a\n  \nb\n""#]);
+        assert_eq!(render_string("a\r\nb\n"), vec![r#""a\r\nb\n""#]);
+        assert_eq!(render_string("a\u{1b}\nb\n"), vec![r#""a\u001b\nb\n""#]);
+    }
+
+    #[test]
+    fn block_indicator_chomping() {
+        assert_eq!(block_indicator("a"), None);
+        assert_eq!(block_indicator("a\n"), Some("|"));
+        assert_eq!(block_indicator("a\nb"), Some("|-"));
+        assert_eq!(block_indicator("\n"), None);
+        assert_eq!(block_indicator("\na\n"), Some("|"));
+        assert_eq!(block_indicator("a\tb\nc\n"), Some("|"));
+    }
+}